@@ -0,0 +1,32 @@
+// Access token for the telemetry server. Opening the dashboard once with
+// ?token=... stores it; it is sent on every request afterwards.
+const STORAGE_KEY = "safewalk-token";
+
+export function getToken(): string | null {
+  if (typeof window === "undefined") return null;
+
+  const fromUrl = new URLSearchParams(window.location.search).get("token");
+  if (fromUrl) {
+    localStorage.setItem(STORAGE_KEY, fromUrl);
+    return fromUrl;
+  }
+
+  return localStorage.getItem(STORAGE_KEY);
+}
+
+// EventSource and WebSocket can't set headers, so the token goes in the query
+export function withToken(url: string): string {
+  const token = getToken();
+  if (!token) return url;
+
+  const separator = url.includes("?") ? "&" : "?";
+  return `${url}${separator}token=${encodeURIComponent(token)}`;
+}
+
+export function authFetch(input: string, init: RequestInit = {}): Promise<Response> {
+  const token = getToken();
+  const headers = new Headers(init.headers);
+  if (token) headers.set("Authorization", `Bearer ${token}`);
+
+  return fetch(input, { ...init, headers });
+}
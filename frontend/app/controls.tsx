@@ -0,0 +1,111 @@
+"use client";
+
+import { useState } from "react";
+import { authFetch } from "./auth";
+
+const PROFILES = ["all", "crossings", "mobility"];
+
+const buttonClass =
+  "px-3 py-2 rounded-md bg-slate-800 text-white text-sm font-medium hover:bg-slate-700 disabled:opacity-50";
+const inputClass = "px-3 py-2 rounded-md border border-slate-300 text-sm";
+
+// Companion controls; every request needs an admin token
+export default function Controls() {
+  const [text, setText] = useState("");
+  const [profile, setProfile] = useState(PROFILES[0]);
+  const [lat, setLat] = useState("");
+  const [lon, setLon] = useState("");
+  const [status, setStatus] = useState<string | null>(null);
+
+  const send = async (method: string, path: string, body?: unknown) => {
+    try {
+      const res = await authFetch(path, {
+        method,
+        headers: body !== undefined ? { "Content-Type": "application/json" } : undefined,
+        body: body !== undefined ? JSON.stringify(body) : undefined,
+      });
+
+      if (res.status === 401 || res.status === 403) {
+        setStatus("Not authorized: open the dashboard with ?token=<admin token>");
+      } else if (!res.ok) {
+        const json = await res.json().catch(() => null);
+        setStatus(json?.error ?? `Request failed (${res.status})`);
+      } else {
+        setStatus("Sent");
+      }
+    } catch (e) {
+      setStatus(e instanceof Error ? e.message : String(e));
+    }
+  };
+
+  return (
+    <div className="space-y-4">
+      <div className="flex gap-2">
+        <input
+          className={`${inputClass} flex-1`}
+          placeholder="Message to speak"
+          value={text}
+          onChange={(e) => setText(e.target.value)}
+        />
+        <button className={buttonClass} disabled={!text} onClick={() => send("POST", "/control/speak", { text })}>
+          Speak
+        </button>
+        <button
+          className={buttonClass}
+          disabled={!text}
+          onClick={() => send("POST", "/control/speak", { text, urgent: true })}
+        >
+          Speak now
+        </button>
+      </div>
+
+      <div className="flex flex-wrap gap-2">
+        <button className={buttonClass} onClick={() => send("POST", "/control/haptics/pause")}>
+          Pause haptics
+        </button>
+        <button className={buttonClass} onClick={() => send("POST", "/control/haptics/resume")}>
+          Resume haptics
+        </button>
+        <button className={buttonClass} onClick={() => send("POST", "/control/motor-test")}>
+          Motor test
+        </button>
+        <button className={buttonClass} onClick={() => send("POST", "/control/overpass/refresh")}>
+          Refresh map data
+        </button>
+        <button className={buttonClass} onClick={() => send("POST", "/control/compass/calibrate")}>
+          Calibrate compass
+        </button>
+      </div>
+
+      <div className="flex gap-2">
+        <select className={inputClass} value={profile} onChange={(e) => setProfile(e.target.value)}>
+          {PROFILES.map((p) => (
+            <option key={p} value={p}>
+              {p}
+            </option>
+          ))}
+        </select>
+        <button className={buttonClass} onClick={() => send("PUT", "/control/hazard-profile", { profile })}>
+          Set hazard profile
+        </button>
+      </div>
+
+      <div className="flex gap-2">
+        <input className={inputClass} placeholder="Latitude" value={lat} onChange={(e) => setLat(e.target.value)} />
+        <input className={inputClass} placeholder="Longitude" value={lon} onChange={(e) => setLon(e.target.value)} />
+        <button
+          className={buttonClass}
+          disabled={!lat || !lon}
+          onClick={() => send("PUT", "/control/destination", { lat: Number(lat), lon: Number(lon) })}
+        >
+          Set destination
+        </button>
+        <button className={buttonClass} onClick={() => send("DELETE", "/control/destination")}>
+          Clear
+        </button>
+      </div>
+
+      {status && <p className="text-sm text-slate-600">{status}</p>}
+    </div>
+  );
+}
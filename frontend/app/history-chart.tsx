@@ -0,0 +1,86 @@
+"use client";
+
+import { useEffect, useState } from "react";
+import { authFetch } from "./auth";
+
+interface Point {
+  t: number;
+  v: number | number[];
+}
+
+const COLORS = ["#2563eb", "#16a34a", "#dc2626", "#9333ea"];
+const WIDTH = 600;
+const HEIGHT = 120;
+
+// Line chart of a telemetry key's recent history. Array values (e.g. motor
+// speeds) are drawn as one line per element.
+export default function HistoryChart({
+  telemetryKey,
+  windowMs = 5 * 60 * 1000,
+}: {
+  telemetryKey: string;
+  windowMs?: number;
+}) {
+  const [points, setPoints] = useState<Point[]>([]);
+  const [unit, setUnit] = useState<string | null>(null);
+
+  useEffect(() => {
+    let cancelled = false;
+
+    const fetchHistory = async () => {
+      const since = Date.now() - windowMs;
+      const res = await authFetch(`/telemetry/${telemetryKey}/history?since=${since}&limit=${WIDTH / 2}`, {
+        cache: "no-store",
+      });
+      if (!res.ok || cancelled) return;
+
+      const body = await res.json();
+      setPoints(body.points);
+      setUnit(body.unit);
+    };
+
+    fetchHistory().catch(() => {});
+    const interval = setInterval(() => fetchHistory().catch(() => {}), 5000);
+
+    return () => {
+      cancelled = true;
+      clearInterval(interval);
+    };
+  }, [telemetryKey, windowMs]);
+
+  if (points.length < 2) {
+    return <p className="text-slate-600">Not enough data</p>;
+  }
+
+  const series: number[][] = Array.isArray(points[0].v)
+    ? points[0].v.map((_, i) => points.map((p) => (p.v as number[])[i]))
+    : [points.map((p) => p.v as number)];
+
+  const values = series.flat();
+  const min = Math.min(...values);
+  const max = Math.max(...values);
+  const tMin = points[0].t;
+  const tMax = points[points.length - 1].t;
+
+  const x = (t: number) => ((t - tMin) / Math.max(tMax - tMin, 1)) * WIDTH;
+  const y = (v: number) => HEIGHT - ((v - min) / Math.max(max - min, 1e-9)) * HEIGHT;
+
+  return (
+    <div>
+      <svg viewBox={`0 0 ${WIDTH} ${HEIGHT}`} className="w-full h-32 bg-slate-50 rounded">
+        {series.map((line, i) => (
+          <polyline
+            key={i}
+            fill="none"
+            stroke={COLORS[i % COLORS.length]}
+            strokeWidth={2}
+            points={line.map((v, j) => `${x(points[j].t)},${y(v)}`).join(" ")}
+          />
+        ))}
+      </svg>
+      <p className="text-xs text-slate-500 mt-1">
+        {min.toFixed(2)} – {max.toFixed(2)} {unit ?? ""}
+      </p>
+    </div>
+  );
+}
@@ -0,0 +1,122 @@
+"use client";
+
+import { useEffect, useRef, useState } from "react";
+import { authFetch, withToken } from "./auth";
+
+interface LogRecord {
+  seq: number;
+  timestamp: number;
+  level: string;
+  target: string;
+  message: string;
+}
+
+const LEVELS = ["error", "warn", "info", "debug", "trace"];
+const MAX_LINES = 500;
+
+const LEVEL_COLORS: Record<string, string> = {
+  ERROR: "text-red-400",
+  WARN: "text-amber-300",
+  INFO: "text-green-300",
+  DEBUG: "text-sky-300",
+  TRACE: "text-slate-400",
+};
+
+// Live view of the device log, e.g. target "safewalk::safewalk" to follow
+// hazard decisions. Changing the device's filter needs an admin token.
+export default function Logs() {
+  const [records, setRecords] = useState<LogRecord[]>([]);
+  const [level, setLevel] = useState("info");
+  const [target, setTarget] = useState("");
+  const [filter, setFilter] = useState("");
+  const [status, setStatus] = useState<string | null>(null);
+  const bottom = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    authFetch("/logs/level")
+      .then((res) => res.json())
+      .then((json) => setFilter(json.filter ?? ""))
+      .catch(() => {});
+  }, []);
+
+  useEffect(() => {
+    setRecords([]);
+
+    const params = new URLSearchParams({ level });
+    if (target) params.set("target", target);
+    const source = new EventSource(withToken(`/logs/stream?${params}`));
+
+    source.onmessage = (event) => {
+      const record = JSON.parse(event.data) as LogRecord;
+      setRecords((prev) => [...prev.slice(-(MAX_LINES - 1)), record]);
+    };
+
+    return () => source.close();
+  }, [level, target]);
+
+  useEffect(() => {
+    bottom.current?.scrollIntoView({ block: "nearest" });
+  }, [records]);
+
+  const applyFilter = async () => {
+    const res = await authFetch("/logs/level", {
+      method: "PUT",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ filter }),
+    });
+    const json = await res.json().catch(() => null);
+    setStatus(res.ok ? `Device filter set to ${json?.filter}` : json?.error ?? `Request failed (${res.status})`);
+  };
+
+  return (
+    <div className="space-y-3">
+      <div className="flex flex-wrap gap-2">
+        <select
+          className="px-3 py-2 rounded-md border border-slate-300 text-sm"
+          value={level}
+          onChange={(e) => setLevel(e.target.value)}
+        >
+          {LEVELS.map((l) => (
+            <option key={l} value={l}>
+              {l}
+            </option>
+          ))}
+        </select>
+        <input
+          className="px-3 py-2 rounded-md border border-slate-300 text-sm flex-1"
+          placeholder="Module, e.g. safewalk::gps"
+          value={target}
+          onChange={(e) => setTarget(e.target.value)}
+        />
+      </div>
+
+      <div className="h-64 overflow-y-auto rounded-md bg-slate-900 p-3 font-mono text-xs">
+        {records.map((r) => (
+          <div key={r.seq} className="whitespace-pre-wrap break-words text-slate-200">
+            <span className="text-slate-500">{new Date(r.timestamp).toLocaleTimeString()} </span>
+            <span className={LEVEL_COLORS[r.level] ?? ""}>{r.level.padEnd(5)} </span>
+            <span className="text-slate-400">{r.target}: </span>
+            {r.message}
+          </div>
+        ))}
+        <div ref={bottom} />
+      </div>
+
+      <div className="flex gap-2">
+        <input
+          className="px-3 py-2 rounded-md border border-slate-300 text-sm flex-1 font-mono"
+          placeholder="info,safewalk::hazard_analyzer=debug"
+          value={filter}
+          onChange={(e) => setFilter(e.target.value)}
+        />
+        <button
+          className="px-3 py-2 rounded-md bg-slate-800 text-white text-sm font-medium hover:bg-slate-700"
+          onClick={() => applyFilter().catch((e) => setStatus(String(e)))}
+        >
+          Set device filter
+        </button>
+      </div>
+      {status && <p className="text-sm text-slate-600">{status}</p>}
+    </div>
+  );
+}
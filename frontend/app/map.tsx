@@ -0,0 +1,187 @@
+"use client";
+
+import { useEffect, useRef, useState } from "react";
+import L from "leaflet";
+import { CircleMarker, GeoJSON, MapContainer, Polyline, useMap } from "react-leaflet";
+import type { FeatureCollection } from "geojson";
+import { authFetch } from "./auth";
+import { decodeTile } from "./mvt";
+
+// Colors of the OSM elements in the vector tiles, by highway tag
+const LINE_COLORS: Record<string, string> = {
+  footway: "#64748b",
+  sidewalk: "#64748b",
+  path: "#94a3b8",
+  pedestrian: "#64748b",
+  steps: "#b45309",
+  primary: "#1e293b",
+  secondary: "#334155",
+  tertiary: "#475569",
+  residential: "#94a3b8",
+};
+
+const SEVERITY_COLORS: Record<string, string> = {
+  High: "#dc2626",
+  Medium: "#f59e0b",
+  Low: "#16a34a",
+};
+
+const EMPTY: FeatureCollection = { type: "FeatureCollection", features: [] };
+
+// Renders the device's own /tiles/{z}/{x}/{y}.mvt onto canvases, so the map
+// works on the device's access point without any external tile service
+class VectorTileLayer extends L.GridLayer {
+  createTile(coords: L.Coords, done: L.DoneCallback): HTMLElement {
+    const canvas = L.DomUtil.create("canvas", "leaflet-tile") as HTMLCanvasElement;
+    const size = this.getTileSize();
+    canvas.width = size.x;
+    canvas.height = size.y;
+
+    authFetch(`/tiles/${coords.z}/${coords.x}/${coords.y}.mvt`)
+      .then((res) => (res.ok ? res.arrayBuffer() : Promise.reject(new Error(`Tile ${res.status}`))))
+      .then((data) => {
+        const ctx = canvas.getContext("2d")!;
+        ctx.fillStyle = "#f8fafc";
+        ctx.fillRect(0, 0, size.x, size.y);
+
+        for (const layer of decodeTile(data)) {
+          const scale = size.x / layer.extent;
+
+          for (const feature of layer.features) {
+            ctx.strokeStyle = LINE_COLORS[feature.properties.highway] ?? "#94a3b8";
+            ctx.fillStyle = "#475569";
+            ctx.lineWidth = 2;
+
+            for (const part of feature.geometry) {
+              if (feature.type === 1) {
+                const [x, y] = part[0];
+                ctx.beginPath();
+                ctx.arc(x * scale, y * scale, 2, 0, 2 * Math.PI);
+                ctx.fill();
+              } else {
+                ctx.beginPath();
+                part.forEach(([x, y], i) => (i === 0 ? ctx.moveTo(x * scale, y * scale) : ctx.lineTo(x * scale, y * scale)));
+                ctx.stroke();
+              }
+            }
+          }
+        }
+
+        done(undefined, canvas);
+      })
+      .catch((e) => done(e, canvas));
+
+    return canvas;
+  }
+}
+
+function VectorTiles() {
+  const map = useMap();
+
+  useEffect(() => {
+    const layer = new VectorTileLayer({ maxZoom: 22 });
+    layer.addTo(map);
+    return () => {
+      layer.remove();
+    };
+  }, [map]);
+
+  return null;
+}
+
+// Centers the map on the first fix, then leaves panning to the user
+function FollowFirstFix({ position }: { position: [number, number] | null }) {
+  const map = useMap();
+  const centered = useRef(false);
+
+  useEffect(() => {
+    if (position && !centered.current) {
+      map.setView(position, 18);
+      centered.current = true;
+    }
+  }, [map, position]);
+
+  return null;
+}
+
+// `heading` is in radians counter-clockwise from east, as sent by the device
+function headingLine(lat: number, lon: number, heading: number, meters = 15): [number, number][] {
+  const dLat = (meters * Math.sin(heading)) / 111320;
+  const dLon = (meters * Math.cos(heading)) / (111320 * Math.cos((lat * Math.PI) / 180));
+  return [
+    [lat, lon],
+    [lat + dLat, lon + dLon],
+  ];
+}
+
+export default function SafeWalkMap({
+  latitude,
+  longitude,
+  heading,
+}: {
+  latitude: number | null;
+  longitude: number | null;
+  heading: number | null;
+}) {
+  const [hazards, setHazards] = useState<FeatureCollection>(EMPTY);
+  const [track, setTrack] = useState<FeatureCollection>(EMPTY);
+  const [version, setVersion] = useState(0);
+
+  useEffect(() => {
+    let cancelled = false;
+
+    const fetchOverlays = async () => {
+      const [hazardsRes, trackRes] = await Promise.all([
+        authFetch("/map/hazards.geojson", { cache: "no-store" }),
+        authFetch("/map/track.geojson", { cache: "no-store" }),
+      ]);
+      if (cancelled || !hazardsRes.ok || !trackRes.ok) return;
+
+      setHazards(await hazardsRes.json());
+      setTrack(await trackRes.json());
+      // GeoJSON layers don't update in place, so remount them
+      setVersion((v) => v + 1);
+    };
+
+    fetchOverlays().catch(() => {});
+    const interval = setInterval(() => fetchOverlays().catch(() => {}), 2000);
+
+    return () => {
+      cancelled = true;
+      clearInterval(interval);
+    };
+  }, []);
+
+  const position: [number, number] | null =
+    latitude !== null && longitude !== null ? [latitude, longitude] : null;
+
+  return (
+    <MapContainer center={[0, 0]} zoom={2} maxZoom={22} className="h-96 w-full rounded-lg">
+      <VectorTiles />
+      <FollowFirstFix position={position} />
+
+      <GeoJSON key={`track-${version}`} data={track} style={{ color: "#2563eb", weight: 3, opacity: 0.6 }} />
+      <GeoJSON
+        key={`hazards-${version}`}
+        data={hazards}
+        style={(feature) => ({ color: SEVERITY_COLORS[feature?.properties?.severity] ?? "#dc2626", weight: 5 })}
+        pointToLayer={(feature, latlng) =>
+          L.circleMarker(latlng, {
+            radius: 7,
+            color: SEVERITY_COLORS[feature.properties?.severity] ?? "#dc2626",
+            fillOpacity: 0.8,
+          }).bindTooltip(String(feature.properties?.kind ?? "Hazard"))
+        }
+      />
+
+      {position && (
+        <>
+          <CircleMarker center={position} radius={8} pathOptions={{ color: "#1d4ed8", fillOpacity: 1 }} />
+          {heading !== null && (
+            <Polyline positions={headingLine(position[0], position[1], heading)} pathOptions={{ color: "#1d4ed8", weight: 4 }} />
+          )}
+        </>
+      )}
+    </MapContainer>
+  );
+}
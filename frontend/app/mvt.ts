@@ -0,0 +1,170 @@
+// Minimal decoder for the vector tiles served by /tiles/{z}/{x}/{y}.mvt.
+// Handles what the server writes: string values, points and line strings.
+
+export interface TileFeature {
+  id: number;
+  type: number; // 1 = point, 2 = line string, 3 = polygon
+  properties: Record<string, string>;
+  // Rings or lines of [x, y] in tile coordinates (0..extent)
+  geometry: [number, number][][];
+}
+
+export interface TileLayer {
+  name: string;
+  extent: number;
+  features: TileFeature[];
+}
+
+class Reader {
+  pos = 0;
+  constructor(private buf: Uint8Array) {}
+
+  get done() {
+    return this.pos >= this.buf.length;
+  }
+
+  varint(): number {
+    let result = 0;
+    let shift = 0;
+    let byte: number;
+    do {
+      byte = this.buf[this.pos++];
+      result += (byte & 0x7f) * 2 ** shift;
+      shift += 7;
+    } while (byte & 0x80);
+    return result;
+  }
+
+  bytes(): Uint8Array {
+    const len = this.varint();
+    const out = this.buf.subarray(this.pos, this.pos + len);
+    this.pos += len;
+    return out;
+  }
+
+  skip(wireType: number) {
+    if (wireType === 0) this.varint();
+    else if (wireType === 1) this.pos += 8;
+    else if (wireType === 2) this.pos += this.varint();
+    else if (wireType === 5) this.pos += 4;
+    else throw new Error(`Unsupported wire type ${wireType}`);
+  }
+
+  packed(): number[] {
+    const inner = new Reader(this.bytes());
+    const out: number[] = [];
+    while (!inner.done) out.push(inner.varint());
+    return out;
+  }
+}
+
+const text = new TextDecoder();
+const zigzag = (n: number) => (n >>> 1) ^ -(n & 1);
+
+function decodeValue(buf: Uint8Array): string {
+  const r = new Reader(buf);
+  while (!r.done) {
+    const key = r.varint();
+    if (key >> 3 === 1 && (key & 7) === 2) return text.decode(r.bytes());
+    r.skip(key & 7);
+  }
+  return "";
+}
+
+function decodeGeometry(commands: number[]): [number, number][][] {
+  const parts: [number, number][][] = [];
+  let x = 0;
+  let y = 0;
+  let i = 0;
+
+  while (i < commands.length) {
+    const id = commands[i] & 7;
+    const count = commands[i] >> 3;
+    i++;
+
+    if (id === 7) {
+      const part = parts[parts.length - 1];
+      if (part?.length) part.push(part[0]);
+      continue;
+    }
+
+    for (let n = 0; n < count; n++) {
+      x += zigzag(commands[i++]);
+      y += zigzag(commands[i++]);
+      if (id === 1) parts.push([[x, y]]);
+      else parts[parts.length - 1].push([x, y]);
+    }
+  }
+
+  return parts;
+}
+
+function decodeLayer(buf: Uint8Array): TileLayer {
+  const r = new Reader(buf);
+  const keys: string[] = [];
+  const values: string[] = [];
+  const raw: { id: number; type: number; tags: number[]; geometry: number[] }[] = [];
+  let name = "";
+  let extent = 4096;
+
+  while (!r.done) {
+    const key = r.varint();
+    switch (key >> 3) {
+      case 1:
+        name = text.decode(r.bytes());
+        break;
+      case 2: {
+        const f = new Reader(r.bytes());
+        const feature = { id: 0, type: 0, tags: [] as number[], geometry: [] as number[] };
+        while (!f.done) {
+          const fkey = f.varint();
+          switch (fkey >> 3) {
+            case 1: feature.id = f.varint(); break;
+            case 2: feature.tags = f.packed(); break;
+            case 3: feature.type = f.varint(); break;
+            case 4: feature.geometry = f.packed(); break;
+            default: f.skip(fkey & 7);
+          }
+        }
+        raw.push(feature);
+        break;
+      }
+      case 3:
+        keys.push(text.decode(r.bytes()));
+        break;
+      case 4:
+        values.push(decodeValue(r.bytes()));
+        break;
+      case 5:
+        extent = r.varint();
+        break;
+      default:
+        r.skip(key & 7);
+    }
+  }
+
+  return {
+    name,
+    extent,
+    features: raw.map((f) => {
+      const properties: Record<string, string> = {};
+      for (let i = 0; i + 1 < f.tags.length; i += 2) {
+        properties[keys[f.tags[i]]] = values[f.tags[i + 1]];
+      }
+      return { id: f.id, type: f.type, properties, geometry: decodeGeometry(f.geometry) };
+    }),
+  };
+}
+
+export function decodeTile(data: ArrayBuffer): TileLayer[] {
+  const r = new Reader(new Uint8Array(data));
+  const layers: TileLayer[] = [];
+
+  while (!r.done) {
+    const key = r.varint();
+    if (key >> 3 === 3) layers.push(decodeLayer(r.bytes()));
+    else r.skip(key & 7);
+  }
+
+  return layers;
+}
@@ -1,96 +1,102 @@
 "use client";
 
 import { useEffect, useState } from "react";
+import dynamic from "next/dynamic";
 import { Card, CardContent, CardHeader, CardTitle } from "@/components/ui/card";
-import {AlertCircle, MapPin, AlertTriangle, Activity, Loader2, Vibrate, Heart} from "lucide-react";
+import {AlertCircle, MapPin, AlertTriangle, Activity, Loader2, Vibrate, Heart, LineChart, SlidersHorizontal, Map as MapIcon, ScrollText} from "lucide-react";
 import { Alert, AlertDescription } from "@/components/ui/alert";
+import HistoryChart from "./history-chart";
+import Controls from "./controls";
+import Logs from "./logs";
+import { authFetch, withToken } from "./auth";
+
+// Leaflet needs `window`, so the map is only rendered in the browser
+const SafeWalkMap = dynamic(() => import("./map"), { ssr: false });
 
 interface SystemStats {
   cpu_usage: number;
   available_memory: number;
 }
 
+interface TelemetryValue {
+  value: unknown;
+  unit: string | null;
+  timestamp: number;
+}
+
 export default function Home() {
-  const [latitude, setLatitude] = useState<string | null>(null);
-  const [longitude, setLongitude] = useState<string | null>(null);
-  const [heading, setHeading] = useState<string | null>(null);
-  const [speeds, setSpeeds] = useState<string[] | null>(null);
-  const [hazards, setHazards] = useState<string[]>([]);
-  const [allTelemetry, setAllTelemetry] = useState<Record<string, string>>({});
+  const [latitude, setLatitude] = useState<number | null>(null);
+  const [longitude, setLongitude] = useState<number | null>(null);
+  const [heading, setHeading] = useState<number | null>(null);
+  const [fixMode, setFixMode] = useState<string | null>(null);
+  const [speeds, setSpeeds] = useState<number[] | null>(null);
+  const [hazards, setHazards] = useState<unknown[]>([]);
+  const [allTelemetry, setAllTelemetry] = useState<Record<string, TelemetryValue>>({});
   const [error, setError] = useState<string | null>(null);
   const [loading, setLoading] = useState(true);
   const [systemStatus, setSystemStatus] = useState<SystemStats | null>(null);
 
   useEffect(() => {
-    let cancelled = false;
-    const fetchTelemetry = async () => {
-      try {
-        setLoading(true);
+    // Telemetry is pushed by the server as it changes; only system health is polled
+    const source = new EventSource(withToken("/telemetry/stream"));
 
-        const latRes = await fetch("/telemetry/latitude", { cache: "no-store" });
-        if (latRes.ok) {
-          const latText = await latRes.text();
-          if (!cancelled) setLatitude(latText === "null" ? null : latText);
-        }
+    source.onopen = () => {
+      setError(null);
+      setLoading(false);
+    };
 
-        const lonRes = await fetch("/telemetry/longitude", { cache: "no-store" });
-        if (lonRes.ok) {
-          const lonText = await lonRes.text();
-          if (!cancelled) setLongitude(lonText === "null" ? null : lonText);
-        }
+    source.onmessage = (event) => {
+      const { key, ...entry } = JSON.parse(event.data) as TelemetryValue & { key: string };
+      const value = entry.value;
 
-        const headingRes = await fetch("/telemetry/heading", { cache: "no-store" });
-        if (headingRes.ok) {
-          const headingText = await headingRes.text();
-          if (!cancelled) setHeading(headingText === "null" ? null : headingText);
-        }
+      setAllTelemetry((prev) => ({ ...prev, [key]: entry }));
 
-        const speedsRes = await fetch("/telemetry/speeds", { cache: "no-store" });
-        if (speedsRes.ok) {
-          const speedsText = await speedsRes.json();
-          if (!cancelled) setSpeeds(speedsText === "null" ? null : speedsText);
-        }
+      switch (key) {
+        case "latitude":
+          setLatitude(typeof value === "number" ? value : null);
+          break;
+        case "longitude":
+          setLongitude(typeof value === "number" ? value : null);
+          break;
+        case "heading":
+          setHeading(typeof value === "number" ? value : null);
+          break;
+        case "fix_mode":
+          setFixMode(typeof value === "string" ? value : null);
+          break;
+        case "speeds":
+          setSpeeds(Array.isArray(value) ? value : null);
+          break;
+        case "hazards":
+          setHazards(Array.isArray(value) ? value : []);
+          break;
+      }
+    };
 
-        const statsRes = await fetch("/health", { cache: "no-store" });
+    // EventSource reconnects by itself; just surface the outage
+    source.onerror = () => {
+      setError("Lost connection to telemetry stream, reconnecting...");
+    };
+
+    let cancelled = false;
+    const fetchHealth = async () => {
+      try {
+        const statsRes = await authFetch("/health", { cache: "no-store" });
         if (statsRes.ok) {
           const healthJson = await statsRes.json();
           if (!cancelled) setSystemStatus(healthJson === "null" ? null : healthJson);
         }
-
-        const hazardsRes = await fetch("/telemetry/hazards", { cache: "no-store" });
-        if (hazardsRes.ok) {
-          const hazardsText = await hazardsRes.text();
-          if (hazardsText !== "null") {
-            try {
-              const parsed = JSON.parse(hazardsText);
-              if (!cancelled) setHazards(Array.isArray(parsed) ? parsed : []);
-            } catch {
-              if (!cancelled) setHazards([]);
-            }
-          }
-        }
-
-        const allRes = await fetch("/telemetry", { cache: "no-store" });
-        if (allRes.ok) {
-          const allData = await allRes.json();
-          const obj: Record<string, string> = {};
-          allData.forEach((item: { key: string; value: string }) => {
-            obj[item.key] = item.value;
-          });
-          if (!cancelled) setAllTelemetry(obj);
-        }
       } catch (e) {
         if (!cancelled) setError(e instanceof Error ? e.message : String(e));
-      } finally {
-        if (!cancelled) setLoading(false);
       }
     };
 
-    fetchTelemetry();
-    const interval = setInterval(fetchTelemetry, 100);
+    fetchHealth();
+    const interval = setInterval(fetchHealth, 2000);
 
     return () => {
       cancelled = true;
+      source.close();
       clearInterval(interval);
     };
   }, []);
@@ -117,6 +123,19 @@ export default function Home() {
             <div className="flex items-center gap-2">
               <MapPin className="h-5 w-5 text-blue-600" />
               <CardTitle className="text-blue-900">Current Location</CardTitle>
+              {fixMode && (
+                <span
+                  className={`ml-auto rounded-full px-3 py-1 text-sm font-medium ${
+                    fixMode === "good"
+                      ? "bg-green-100 text-green-800"
+                      : fixMode === "degraded"
+                        ? "bg-amber-100 text-amber-800"
+                        : "bg-red-100 text-red-800"
+                  }`}
+                >
+                  GPS {fixMode}
+                </span>
+              )}
             </div>
           </CardHeader>
           <CardContent className="pt-6">
@@ -124,25 +143,38 @@ export default function Home() {
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Latitude</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {latitude || "—"}
+                  {latitude?.toFixed(6) ?? "—"}
                 </p>
               </div>
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Longitude</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {longitude || "—"}
+                  {longitude?.toFixed(6) ?? "—"}
                 </p>
               </div>
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Heading</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {heading || "—"}
+                  {heading !== null ? `${((heading * 180) / Math.PI).toFixed(1)}°` : "—"}
                 </p>
               </div>
             </div>
           </CardContent>
         </Card>
 
+        {/* Map Card */}
+        <Card className="shadow-lg">
+          <CardHeader>
+            <div className="flex items-center gap-2">
+              <MapIcon className="h-5 w-5 text-blue-600" />
+              <CardTitle className="text-blue-900">Map</CardTitle>
+            </div>
+          </CardHeader>
+          <CardContent className="pt-6">
+            <SafeWalkMap latitude={latitude} longitude={longitude} heading={heading} />
+          </CardContent>
+        </Card>
+
         {/* Hazards Card */}
         <Card className="shadow-lg">
           <CardHeader>
@@ -171,31 +203,81 @@ export default function Home() {
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Front</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {speeds && speeds[0]}
+                  {speeds?.[0]?.toFixed(2)}
                 </p>
               </div>
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Right</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {speeds && speeds[1]}
+                  {speeds?.[1]?.toFixed(2)}
                 </p>
               </div>
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Back</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {speeds && speeds[2]}
+                  {speeds?.[2]?.toFixed(2)}
                 </p>
               </div>
               <div>
                 <p className="text-sm font-medium text-slate-600 mb-2">Left</p>
                 <p className="text-3xl font-mono font-bold text-slate-900">
-                  {speeds && speeds[3]}
+                  {speeds?.[3]?.toFixed(2)}
                 </p>
               </div>
             </div>
           </CardContent>
         </Card>
 
+        {/* Controls */}
+        <Card className="shadow-lg">
+          <CardHeader>
+            <div className="flex items-center gap-2">
+              <SlidersHorizontal className="h-5 w-5 text-slate-700" />
+              <CardTitle className="text-slate-900">Controls</CardTitle>
+            </div>
+          </CardHeader>
+          <CardContent className="pt-6">
+            <Controls />
+          </CardContent>
+        </Card>
+
+        {/* Logs */}
+        <Card className="shadow-lg">
+          <CardHeader>
+            <div className="flex items-center gap-2">
+              <ScrollText className="h-5 w-5 text-slate-700" />
+              <CardTitle className="text-slate-900">Device Log</CardTitle>
+            </div>
+          </CardHeader>
+          <CardContent className="pt-6">
+            <Logs />
+          </CardContent>
+        </Card>
+
+        {/* History */}
+        <Card className="shadow-lg">
+          <CardHeader>
+            <div className="flex items-center gap-2">
+              <LineChart className="h-5 w-5 text-blue-600" />
+              <CardTitle className="text-blue-900">History</CardTitle>
+            </div>
+          </CardHeader>
+          <CardContent className="pt-6 space-y-6">
+            <div>
+              <p className="text-sm font-medium text-slate-600 mb-2">Heading</p>
+              <HistoryChart telemetryKey="heading" />
+            </div>
+            <div>
+              <p className="text-sm font-medium text-slate-600 mb-2">Nearest Hazard Distance</p>
+              <HistoryChart telemetryKey="hazard_distance" />
+            </div>
+            <div>
+              <p className="text-sm font-medium text-slate-600 mb-2">Motor Speeds (front, right, back, left)</p>
+              <HistoryChart telemetryKey="speeds" />
+            </div>
+          </CardContent>
+        </Card>
+
         {/* All Telemetry */}
         <Card className="shadow-lg">
           <CardHeader>
@@ -209,13 +291,17 @@ export default function Home() {
               <p className="text-slate-600">No telemetry data available</p>
             ) : (
               <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4">
-                {Object.entries(allTelemetry).map(([key, value]) => (
+                {Object.entries(allTelemetry).map(([key, entry]) => (
                   <div key={key} className="bg-slate-50 border border-slate-200 rounded-lg p-4">
                     <p className="text-xs font-bold text-slate-600 uppercase tracking-wide mb-2">
                       {key}
                     </p>
                     <p className="text-sm font-mono text-slate-900 break-words max-h-20 overflow-y-auto">
-                      {value}
+                      {JSON.stringify(entry.value)}
+                      {entry.unit && <span className="text-slate-500"> {entry.unit}</span>}
+                    </p>
+                    <p className="text-xs text-slate-500 mt-1">
+                      {new Date(entry.timestamp).toLocaleTimeString()}
                     </p>
                   </div>
                 ))}
@@ -0,0 +1,118 @@
+use crate::hazard_analyzer::HazardReport;
+use crate::locale::Catalog;
+
+// 12 o'clock is straight ahead; negative relative angles are to the right,
+// so they map to 1-5 o'clock.
+pub fn clock_position(relative_angle: f64) -> u8 {
+    let hour = ((-relative_angle.to_degrees() / 30.0).round() as i64).rem_euclid(12);
+
+    if hour == 0 { 12 } else { hour as u8 }
+}
+
+// Builds the spoken description of a hazard, e.g. "Unmarked crossing, 2 o'clock, 12 meters".
+// Without a heading the direction is unknown and is left out.
+pub fn describe(report: &HazardReport, heading: Option<f64>, catalog: &Catalog) -> String {
+    let kind = catalog.hazard_kind(report.kind);
+    let distance = catalog.distance(report.distance_m);
+
+    match heading.map(|h| clock_position(report.vector.relative_to(h).rotation)) {
+        Some(12) => catalog.get("hazard.ahead", &[("kind", &kind), ("distance", &distance)]),
+        Some(hour) => catalog.get(
+            "hazard.clock",
+            &[
+                ("kind", &kind),
+                ("hour", &hour.to_string()),
+                ("distance", &distance),
+            ],
+        ),
+        None => catalog.get("hazard.undirected", &[("kind", &kind), ("distance", &distance)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::announcement::{clock_position, describe};
+    use crate::gps::Vector;
+    use crate::hazard_analyzer::{HazardKind, HazardReport, HazardSeverity};
+    use crate::locale::{Catalog, Locale, Units};
+    use crate::overpass::Element;
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+
+    fn report(tags: &[(&str, &str)], rotation: f64, distance_m: f64) -> HazardReport {
+        let hazard = Element::Node {
+            id: 1,
+            lat: 0.0,
+            lon: 0.0,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        };
+
+        HazardReport {
+            kind: HazardKind::classify(&hazard),
+            hazard,
+            distance: 0.0001,
+            distance_m,
+            severity: HazardSeverity::High,
+            vector: Vector::new(rotation, 0.0001),
+        }
+    }
+
+    #[test]
+    fn clock_positions() {
+        assert_eq!(clock_position(0.0), 12);
+        assert_eq!(clock_position(-PI / 3.0), 2);
+        assert_eq!(clock_position(-PI / 2.0), 3);
+        assert_eq!(clock_position(PI / 2.0), 9);
+        assert_eq!(clock_position(PI), 6);
+        assert_eq!(clock_position(-PI), 6);
+    }
+
+    #[test]
+    fn announcements() {
+        let catalog = Catalog::new(Locale::En, Units::Metric);
+
+        // Heading north (π/2), hazard 60° clockwise of north
+        let crossing = report(
+            &[("highway", "crossing"), ("crossing", "unmarked")],
+            PI / 2.0 - PI / 3.0,
+            12.3,
+        );
+        assert_eq!(
+            describe(&crossing, Some(PI / 2.0), &catalog),
+            "Unmarked crossing, 2 o'clock, 12 meters"
+        );
+
+        let steps = report(&[("highway", "steps")], PI / 2.0, 4.6);
+        assert_eq!(
+            describe(&steps, Some(PI / 2.0), &catalog),
+            "Steps without handrail ahead, 5 meters"
+        );
+
+        let kerb = report(&[("kerb", "raised")], 0.0, 0.4);
+        assert_eq!(describe(&kerb, None, &catalog), "Raised kerb, 1 meter");
+    }
+
+    #[test]
+    fn localized_announcements() {
+        let crossing = report(
+            &[("highway", "crossing"), ("crossing", "unmarked")],
+            PI / 2.0 - PI / 3.0,
+            12.3,
+        );
+
+        let german = Catalog::new(Locale::De, Units::Metric);
+        assert_eq!(
+            describe(&crossing, Some(PI / 2.0), &german),
+            "Unmarkierter Übergang, 2 Uhr, 12 Meter"
+        );
+
+        let spanish = Catalog::new(Locale::Es, Units::Imperial);
+        assert_eq!(
+            describe(&crossing, Some(PI / 2.0), &spanish),
+            "Cruce sin marcar, a las 2, 40 pies"
+        );
+    }
+}
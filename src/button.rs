@@ -2,6 +2,10 @@ use rppal::gpio::{Gpio, InputPin};
 use std::time::Duration;
 use tokio::time::sleep;
 
+pub trait ButtonInput: Send {
+    fn is_pressed(&self) -> bool;
+}
+
 pub struct Button {
     pin: InputPin,
 }
@@ -24,3 +28,9 @@ impl Button {
         }
     }
 }
+
+impl ButtonInput for Button {
+    fn is_pressed(&self) -> bool {
+        Button::is_pressed(self)
+    }
+}
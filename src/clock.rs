@@ -0,0 +1,123 @@
+use crate::gps::GNRMC;
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    // IANA name such as "America/Phoenix" for local times; UTC when unset
+    pub timezone: Option<Tz>,
+    // The Pi has no RTC, so without network its clock starts wherever it
+    // stopped. Sets it from GNSS time unless NTP has synchronised it.
+    pub sync_from_gps: bool,
+    // Smaller differences are left alone
+    pub max_drift_secs: f64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            timezone: None,
+            sync_from_gps: false,
+            max_drift_secs: 2.0,
+        }
+    }
+}
+
+pub struct Clock {
+    timezone: Tz,
+    max_drift_secs: f64,
+    // Done once per run: after a sync, a check that found no drift, or a failure
+    sync_pending: bool,
+}
+
+impl Clock {
+    // `sync` is false for sources whose time isn't now, like recordings
+    pub fn new(config: &ClockConfig, sync: bool) -> Self {
+        Self {
+            timezone: config.timezone.unwrap_or(Tz::UTC),
+            max_drift_secs: config.max_drift_secs,
+            sync_pending: sync && config.sync_from_gps,
+        }
+    }
+
+    pub fn local(&self, time: DateTime<Utc>) -> DateTime<Tz> {
+        time.with_timezone(&self.timezone)
+    }
+
+    // Checks and sets the system clock in the background, as both run commands
+    pub fn sync(&mut self, fix: &GNRMC) {
+        if !self.sync_pending || fix.status != 1 {
+            return;
+        }
+        let Some(time) = fix.time else {
+            return;
+        };
+        self.sync_pending = false;
+
+        tokio::spawn(sync_system_clock(time, self.max_drift_secs));
+    }
+}
+
+async fn sync_system_clock(time: DateTime<Utc>, max_drift_secs: f64) {
+    let drift = (time - Utc::now()).as_seconds_f64();
+    if drift.abs() <= max_drift_secs {
+        return;
+    }
+    if ntp_synchronized().await {
+        info!("GPS time differs from the system clock by {:.1} s; leaving it to NTP", drift);
+        return;
+    }
+
+    match set_system_clock(time).await {
+        Ok(()) => info!("System clock set from GPS, moved by {:.1} s", drift),
+        Err(e) => warn!("Failed to set the system clock: {}", e),
+    }
+}
+
+async fn ntp_synchronized() -> bool {
+    Command::new("timedatectl")
+        .args(["show", "--property=NTPSynchronized", "--value"])
+        .output()
+        .await
+        .is_ok_and(|output| output.stdout.trim_ascii() == b"yes")
+}
+
+async fn set_system_clock(time: DateTime<Utc>) -> Result<()> {
+    let output = Command::new("date")
+        .arg("--utc")
+        .arg("--set")
+        .arg(time.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!("date: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, ClockConfig};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn local_time_from_timezone() {
+        let config: ClockConfig = serde_json::from_str(r#"{"timezone": "America/Phoenix"}"#).unwrap();
+        let clock = Clock::new(&config, true);
+
+        let time = Utc.with_ymd_and_hms(2025, 7, 1, 3, 30, 0).unwrap();
+        assert_eq!(clock.local(time).to_rfc3339(), "2025-06-30T20:30:00-07:00");
+
+        let utc = Clock::new(&ClockConfig::default(), true);
+        assert_eq!(utc.local(time).to_rfc3339(), "2025-07-01T03:30:00+00:00");
+
+        assert!(serde_json::from_str::<ClockConfig>(r#"{"timezone": "Mars/Olympus"}"#).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::compass::{Imu, ImuReading};
+use anyhow::{Context, Result};
+use rppal::i2c::I2c;
+use serde::{Deserialize, Serialize};
+
+const ACCEL_ADDRESS: u16 = 0x19;
+const MAG_ADDRESS: u16 = 0x1E;
+
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG4_A: u8 = 0x23;
+// Setting the top bit of the register address reads consecutive registers
+const OUT_X_L_A_AUTO_INCREMENT: u8 = 0x28 | 0x80;
+
+const CRA_REG_M: u8 = 0x00;
+const CRB_REG_M: u8 = 0x01;
+const MR_REG_M: u8 = 0x02;
+const OUT_X_H_M: u8 = 0x03;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Lsm303Options {
+    pub bus: u8,
+}
+
+impl Default for Lsm303Options {
+    fn default() -> Self {
+        Self { bus: 1 }
+    }
+}
+
+// LSM303DLHC accelerometer + magnetometer. Expects the board mounted level,
+// component side up, with its x axis pointing forward.
+pub struct Lsm303 {
+    i2c: I2c,
+}
+
+impl Lsm303 {
+    pub fn new(options: &Lsm303Options) -> Result<Self> {
+        let mut i2c = I2c::with_bus(options.bus)
+            .with_context(|| format!("Failed to open I2C bus {}", options.bus))?;
+
+        i2c.set_slave_address(ACCEL_ADDRESS)?;
+        // 100 Hz, all axes on
+        i2c.smbus_write_byte(CTRL_REG1_A, 0x57)?;
+        // ±2 g, high resolution
+        i2c.smbus_write_byte(CTRL_REG4_A, 0x08)?;
+
+        i2c.set_slave_address(MAG_ADDRESS)?;
+        // 30 Hz
+        i2c.smbus_write_byte(CRA_REG_M, 0x14)?;
+        // ±1.3 gauss
+        i2c.smbus_write_byte(CRB_REG_M, 0x20)?;
+        // Continuous conversion
+        i2c.smbus_write_byte(MR_REG_M, 0x00)?;
+
+        Ok(Self { i2c })
+    }
+}
+
+impl Imu for Lsm303 {
+    fn read(&mut self) -> Result<ImuReading> {
+        let mut accel = [0u8; 6];
+        self.i2c.set_slave_address(ACCEL_ADDRESS)?;
+        self.i2c.write_read(&[OUT_X_L_A_AUTO_INCREMENT], &mut accel)?;
+
+        // Little endian, 12 bits left-justified, 1 mg per LSB
+        let accel = [0, 1, 2].map(|axis| {
+            (i16::from_le_bytes([accel[axis * 2], accel[axis * 2 + 1]]) >> 4) as f64 / 1000.0
+        });
+
+        let mut mag = [0u8; 6];
+        self.i2c.set_slave_address(MAG_ADDRESS)?;
+        self.i2c.write_read(&[OUT_X_H_M], &mut mag)?;
+
+        // Big endian, in X, Z, Y order; 1100 LSB/gauss for X and Y, 980 for Z
+        let value = |i: usize| i16::from_be_bytes([mag[i], mag[i + 1]]) as f64;
+        let (mx, mz, my) = (value(0) / 1100.0, value(2) / 980.0, value(4) / 1100.0);
+
+        // The sensor's y points left and z up, and the accelerometer measures
+        // the reaction to gravity rather than gravity itself
+        Ok(ImuReading {
+            magnetic: [mx, -my, -mz],
+            gravity: [-accel[0], accel[1], accel[2]],
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use crate::compass::{Imu, ImuReading};
+use anyhow::Result;
+
+// Horizontal and downward parts of the Earth's field, gauss; roughly Arizona
+const FIELD_NORTH: f64 = 0.24;
+const FIELD_DOWN: f64 = 0.40;
+
+// Produces the readings a perfect sensor would give in a fixed orientation
+#[derive(Debug, Clone, Copy)]
+pub struct MockImu {
+    pub reading: ImuReading,
+}
+
+impl MockImu {
+    // Heading clockwise from magnetic north, pitch nose up, roll right side
+    // down, all in degrees
+    pub fn pointing(heading_deg: f64, pitch_deg: f64, roll_deg: f64) -> Self {
+        let (psi, theta, phi) = (
+            heading_deg.to_radians(),
+            pitch_deg.to_radians(),
+            roll_deg.to_radians(),
+        );
+
+        // World (north, east, down) to body: Rx(roll) Ry(pitch) Rz(heading)
+        let to_body = |[n, e, d]: [f64; 3]| {
+            let (x, y, z) = (psi.cos() * n + psi.sin() * e, -psi.sin() * n + psi.cos() * e, d);
+            let (x, y, z) = (theta.cos() * x - theta.sin() * z, y, theta.sin() * x + theta.cos() * z);
+            [x, phi.cos() * y + phi.sin() * z, -phi.sin() * y + phi.cos() * z]
+        };
+
+        Self {
+            reading: ImuReading {
+                magnetic: to_body([FIELD_NORTH, 0.0, FIELD_DOWN]),
+                gravity: to_body([0.0, 0.0, 1.0]),
+            },
+        }
+    }
+
+    // Adds a hard-iron offset and per-axis soft-iron scaling
+    #[cfg(test)]
+    pub fn distorted(mut self, offset: [f64; 3], scale: [f64; 3]) -> Self {
+        for axis in 0..3 {
+            self.reading.magnetic[axis] = self.reading.magnetic[axis] * scale[axis] + offset[axis];
+        }
+        self
+    }
+}
+
+impl Imu for MockImu {
+    fn read(&mut self) -> Result<ImuReading> {
+        Ok(self.reading)
+    }
+}
@@ -0,0 +1,307 @@
+mod lsm303;
+mod mock;
+
+pub use lsm303::*;
+pub use mock::*;
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// Both vectors are in the body frame of the wearer: x forward, y right,
+// z down. Units don't matter, only directions.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct ImuReading {
+    pub magnetic: [f64; 3],
+    // Direction of gravity; +z when level
+    pub gravity: [f64; 3],
+}
+
+pub trait Imu: Send {
+    fn read(&mut self) -> Result<ImuReading>;
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompassSensor {
+    Lsm303(Lsm303Options),
+    // A sensor that always faces one way, for bench runs without hardware
+    Mock { heading_deg: f64 },
+}
+
+impl CompassSensor {
+    pub fn build(&self) -> Result<Box<dyn Imu>> {
+        Ok(match self {
+            CompassSensor::Lsm303(options) => Box::new(Lsm303::new(options)?),
+            CompassSensor::Mock { heading_deg } => {
+                Box::new(MockImu::pointing(*heading_deg, 0.0, 0.0))
+            }
+        })
+    }
+}
+
+// m' = soft_iron * (m - hard_iron)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub hard_iron: [f64; 3],
+    pub soft_iron: [[f64; 3]; 3],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            hard_iron: [0.0; 3],
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl Calibration {
+    // Fits offsets and scale of the horizontal axes to raw readings taken
+    // while the wearer turns around on the spot. Z is left alone: a wearer
+    // can't turn the sensor upside down, so its range is never covered, and
+    // it only matters when leaning. Returns None without a full turn.
+    pub fn fit(samples: &[[f64; 3]]) -> Option<Self> {
+        let mut min = [f64::MAX; 2];
+        let mut max = [f64::MIN; 2];
+        for sample in samples {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(sample[axis]);
+                max[axis] = max[axis].max(sample[axis]);
+            }
+        }
+
+        let radius = [0, 1].map(|axis| (max[axis] - min[axis]) / 2.0);
+        if radius.iter().any(|r| *r <= f64::EPSILON) {
+            return None;
+        }
+        let mean_radius = (radius[0] + radius[1]) / 2.0;
+
+        let mut calibration = Self::default();
+        for axis in 0..2 {
+            calibration.hard_iron[axis] = (max[axis] + min[axis]) / 2.0;
+            calibration.soft_iron[axis][axis] = mean_radius / radius[axis];
+        }
+
+        Some(calibration)
+    }
+
+    pub fn apply(&self, magnetic: [f64; 3]) -> [f64; 3] {
+        let centered = [0, 1, 2].map(|axis| magnetic[axis] - self.hard_iron[axis]);
+
+        self.soft_iron
+            .map(|row| row[0] * centered[0] + row[1] * centered[1] + row[2] * centered[2])
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompassConfig {
+    // None when no compass is fitted
+    pub sensor: Option<CompassSensor>,
+    pub calibration: Calibration,
+    // Magnetic declination at the walking area, east positive
+    pub declination_deg: f64,
+    // How far the sensor's x axis is turned clockwise from the wearer's front
+    pub mounting_offset_deg: f64,
+    // Typical error of the calibrated heading
+    pub accuracy_deg: f64,
+}
+
+impl Default for CompassConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            calibration: Calibration::default(),
+            declination_deg: 0.0,
+            mounting_offset_deg: 0.0,
+            accuracy_deg: 10.0,
+        }
+    }
+}
+
+// Magnetic heading of the body x axis, radians clockwise from magnetic
+// north, with pitch and roll taken out using gravity (Freescale AN4248)
+pub fn tilt_compensated(magnetic: [f64; 3], gravity: [f64; 3]) -> f64 {
+    let [bx, by, bz] = magnetic;
+    let [gx, gy, gz] = gravity;
+
+    let roll = gy.atan2(gz);
+    let pitch = (-gx).atan2(gy * roll.sin() + gz * roll.cos());
+
+    let y = bz * roll.sin() - by * roll.cos();
+    let x = bx * pitch.cos() + by * pitch.sin() * roll.sin() + bz * pitch.sin() * roll.cos();
+
+    y.atan2(x)
+}
+
+// Converts a bearing clockwise from north into the heading convention used
+// for hazard vectors: counter-clockwise from east in lat/lon space
+pub fn bearing_to_heading(bearing: f64, latitude: f64) -> f64 {
+    (bearing.cos() * latitude.to_radians().cos()).atan2(bearing.sin())
+}
+
+pub fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+pub struct Compass {
+    imu: Box<dyn Imu>,
+    config: CompassConfig,
+    // Raw magnetometer samples collected until the deadline
+    calibrating: Option<(Instant, Vec<[f64; 3]>)>,
+}
+
+impl Compass {
+    pub fn new(imu: Box<dyn Imu>, config: CompassConfig) -> Self {
+        Self {
+            imu,
+            config,
+            calibrating: None,
+        }
+    }
+
+    // Collects readings for `duration` while the wearer turns around slowly,
+    // then replaces the calibration. The result is logged for config.json.
+    pub fn calibrate(&mut self, duration: Duration) {
+        info!("Calibrating compass for {} s", duration.as_secs());
+        self.calibrating = Some((Instant::now() + duration, Vec::new()));
+    }
+
+    fn collect_calibration(&mut self, magnetic: [f64; 3]) {
+        let Some((until, samples)) = &mut self.calibrating else {
+            return;
+        };
+        samples.push(magnetic);
+        if Instant::now() < *until {
+            return;
+        }
+
+        match Calibration::fit(samples) {
+            Some(calibration) => {
+                info!(
+                    "Compass calibrated: {}",
+                    serde_json::to_string(&calibration).unwrap()
+                );
+                self.config.calibration = calibration;
+            }
+            None => warn!("Compass calibration failed; turn through more directions"),
+        }
+        self.calibrating = None;
+    }
+
+    // Direction the wearer faces, in the hazard vector convention, and the
+    // raw reading it came from
+    pub fn read(&mut self, latitude: f64) -> Result<(f64, ImuReading)> {
+        let reading = self.imu.read()?;
+        self.collect_calibration(reading.magnetic);
+        let magnetic = self.config.calibration.apply(reading.magnetic);
+
+        let bearing = tilt_compensated(magnetic, reading.gravity)
+            + (self.config.declination_deg - self.config.mounting_offset_deg).to_radians();
+
+        Ok((bearing_to_heading(bearing, latitude), reading))
+    }
+}
+
+// Portion of the difference between GPS course and compass taken into the
+// compass correction per update while walking
+const OFFSET_RATE: f64 = 0.02;
+
+// Combines the compass with the course over ground. While walking with a
+// good course the compass's remaining bias (body sway, declination error,
+// leftover iron) is learned, so the heading stays right after stopping.
+pub struct HeadingFusion {
+    compass_accuracy: f64,
+    offset: f64,
+}
+
+impl HeadingFusion {
+    pub fn new(compass_accuracy: f64) -> Self {
+        Self {
+            compass_accuracy,
+            offset: 0.0,
+        }
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.compass_accuracy
+    }
+
+    // `course` is the GPS heading with its accuracy, both in radians
+    pub fn update(&mut self, compass: Option<f64>, course: Option<(f64, f64)>) -> Option<f64> {
+        let compass = compass.map(|heading| wrap_angle(heading + self.offset));
+
+        match (compass, course) {
+            (Some(compass), Some((course, accuracy))) => {
+                let difference = wrap_angle(course - compass);
+                if accuracy < self.compass_accuracy {
+                    self.offset = wrap_angle(self.offset + OFFSET_RATE * difference);
+                }
+
+                // Inverse-variance weighting of the two
+                let weight = self.compass_accuracy.powi(2)
+                    / (self.compass_accuracy.powi(2) + accuracy.powi(2));
+                Some(wrap_angle(compass + weight * difference))
+            }
+            (Some(compass), None) => Some(compass),
+            (None, Some((course, _))) => Some(course),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compass::{
+        Calibration, Compass, CompassConfig, HeadingFusion, MockImu, bearing_to_heading,
+    };
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn tilt_calibration_and_fusion() {
+        let config = CompassConfig::default();
+
+        // Facing east while leaning forward and to the side
+        let mut compass = Compass::new(Box::new(MockImu::pointing(90.0, 15.0, -10.0)), config.clone());
+        assert!(compass.read(0.0).unwrap().0.abs() < 1e-9);
+
+        // Hard and soft iron distort the raw field; fitting on a full turn
+        // undoes it
+        let distorted = |imu: MockImu| imu.distorted([0.2, -0.1, 0.0], [1.3, 0.8, 1.0]);
+        let samples = (0..36)
+            .map(|i| distorted(MockImu::pointing(i as f64 * 10.0, 0.0, 0.0)).reading.magnetic)
+            .collect::<Vec<_>>();
+        let calibration = Calibration::fit(&samples).unwrap();
+
+        let imu = distorted(MockImu::pointing(200.0, 0.0, 0.0));
+        let (uncalibrated, _) = Compass::new(Box::new(imu), config).read(0.0).unwrap();
+        let (calibrated, _) = Compass::new(
+            Box::new(imu),
+            CompassConfig {
+                calibration,
+                ..Default::default()
+            },
+        )
+        .read(0.0)
+        .unwrap();
+
+        let expected = bearing_to_heading(200f64.to_radians(), 0.0);
+        assert!((calibrated - expected).abs() < 0.05, "{} vs {}", calibrated, expected);
+        assert!((uncalibrated - expected).abs() > 0.05);
+
+        // Walking north with a compass reading 20° off: the bias is learned,
+        // and standing still afterwards keeps the corrected heading
+        let mut fusion = HeadingFusion::new(10f64.to_radians());
+        let compass_heading = FRAC_PI_2 + 20f64.to_radians();
+        for _ in 0..300 {
+            fusion.update(Some(compass_heading), Some((FRAC_PI_2, 3f64.to_radians())));
+        }
+        let standing = fusion.update(Some(compass_heading), None).unwrap();
+        assert!((standing - FRAC_PI_2).abs() < 1f64.to_radians(), "{}", standing.to_degrees());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::clock::ClockConfig;
+use crate::compass::CompassConfig;
+use crate::dead_reckoning::DeadReckoningConfig;
+use crate::earcon::EarconConfig;
+use crate::fix_quality::FixQualityConfig;
+use crate::hazard_analyzer::HazardProfile;
+use crate::kalman::KalmanConfig;
+use crate::locale::{Locale, Units};
+use crate::logging::LogConfig;
+use crate::networking::ServerConfig;
+use crate::position::PositionSourceConfig;
+use crate::session::SessionConfig;
+use crate::speech::SpeechBackend;
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// Loaded from config.json next to the binary. Every field has a default, so
+// the file only needs to contain the settings that differ.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    // Language of spoken messages; also picks the espeak voice unless one is set
+    pub locale: Locale,
+    pub units: Units,
+    pub speech: SpeechConfig,
+    pub earcons: EarconConfig,
+    pub server: ServerConfig,
+    // Initial hazard profile; can be changed at runtime from the dashboard
+    pub hazard_profile: HazardProfile,
+    pub log: LogConfig,
+    // Where fixes come from: the GPS module, or a GPX/NMEA recording
+    pub position: PositionSourceConfig,
+    // Smoothing of fixes into position, speed and heading
+    pub kalman: KalmanConfig,
+    // Optional magnetometer for heading while standing still
+    pub compass: CompassConfig,
+    // Step counting to carry the position through GPS outages; needs a compass
+    pub dead_reckoning: DeadReckoningConfig,
+    // When the fix counts as good, degraded or lost
+    pub fix_quality: FixQualityConfig,
+    pub session: SessionConfig,
+    // Time zone for local times, and setting the system clock from GNSS
+    pub clock: ClockConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpeechConfig {
+    pub backend: SpeechBackend,
+    // Identical phrases within this window are only spoken once
+    pub repeat_window_secs: u64,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        Self {
+            backend: SpeechBackend::default(),
+            repeat_window_secs: 10,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            info!("No config at {}, using defaults", path.display());
+            return Ok(Config::default());
+        }
+
+        let data = fs::read_to_string(path)?;
+
+        serde_json::from_str(&data).with_context(|| format!("Invalid config {}", path.display()))
+    }
+}
@@ -0,0 +1,143 @@
+use crate::compass::ImuReading;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// Faster than anyone walks; shorter gaps are the same step ringing
+const MIN_STEP_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeadReckoningConfig {
+    pub step_length_m: f64,
+    // Standard deviation of the step length as a fraction of it
+    pub step_length_error: f64,
+    // Acceleration magnitude, in g, that counts as a heel strike
+    pub step_threshold_g: f64,
+    // Above this position error hazard warnings are announced as unreliable
+    pub max_accuracy_m: f64,
+}
+
+impl Default for DeadReckoningConfig {
+    fn default() -> Self {
+        Self {
+            step_length_m: 0.7,
+            step_length_error: 0.15,
+            step_threshold_g: 1.12,
+            max_accuracy_m: 15.0,
+        }
+    }
+}
+
+// Counts heel strikes as peaks in the acceleration magnitude. A step is
+// registered when the magnitude rises above the threshold and re-armed once
+// it falls back below 1 g.
+pub struct StepDetector {
+    threshold: f64,
+    armed: bool,
+    last_step: Option<Instant>,
+}
+
+impl StepDetector {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            armed: true,
+            last_step: None,
+        }
+    }
+
+    pub fn update(&mut self, reading: &ImuReading, now: Instant) -> bool {
+        let [x, y, z] = reading.gravity;
+        let magnitude = (x * x + y * y + z * z).sqrt();
+
+        if magnitude < 1.0 {
+            self.armed = true;
+            return false;
+        }
+        if !self.armed || magnitude < self.threshold {
+            return false;
+        }
+        if self
+            .last_step
+            .is_some_and(|last| now.duration_since(last) < MIN_STEP_INTERVAL)
+        {
+            return false;
+        }
+
+        self.armed = false;
+        self.last_step = Some(now);
+        true
+    }
+}
+
+// Displacement of one step in meters (east, north) and its variance.
+// `heading` is counter-clockwise from east in lat/lon space and
+// `heading_accuracy` its standard deviation in radians.
+pub fn step(
+    config: &DeadReckoningConfig,
+    heading: f64,
+    heading_accuracy: f64,
+    latitude: f64,
+) -> ((f64, f64), f64) {
+    let east = heading.cos() * latitude.to_radians().cos();
+    let north = heading.sin();
+    let norm = east.hypot(north);
+
+    let length = config.step_length_m;
+    let variance = (length * config.step_length_error).powi(2) + (length * heading_accuracy).powi(2);
+
+    ((length * east / norm, length * north / norm), variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compass::ImuReading;
+    use crate::dead_reckoning::{DeadReckoningConfig, StepDetector, step};
+    use crate::gps::GNRMC;
+    use crate::kalman::{KalmanConfig, KalmanFilter};
+    use crate::overpass::Point;
+    use std::f64::consts::{FRAC_PI_2, PI};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn steps_carry_position_through_outage() {
+        let config = DeadReckoningConfig::default();
+
+        // Two steps a second for 5 s, sampled at 10 Hz
+        let start = Instant::now();
+        let mut detector = StepDetector::new(config.step_threshold_g);
+        let steps = (0..50)
+            .filter(|i| {
+                let t = *i as f64 / 10.0;
+                let reading = ImuReading {
+                    magnetic: [0.0; 3],
+                    gravity: [0.0, 0.0, 1.0 + 0.3 * (2.0 * PI * 2.0 * t).sin()],
+                };
+                detector.update(&reading, start + Duration::from_secs_f64(t))
+            })
+            .count();
+        assert_eq!(steps, 10);
+
+        // Ten steps north from a good fix
+        let origin = Point {
+            lat: 33.4235,
+            lon: -111.9328,
+        };
+        let mut filter = KalmanFilter::new(KalmanConfig::default());
+        let fixed = filter.update(&GNRMC::from_point(origin), 0.0).unwrap();
+
+        let mut estimate = fixed;
+        for _ in 0..10 {
+            let (displacement, variance) =
+                step(&config, FRAC_PI_2, 10f64.to_radians(), origin.lat);
+            estimate = filter.dead_reckon(0.5, Some(displacement), variance).unwrap();
+        }
+
+        let north = estimate.position.distance_m(&origin);
+        assert!((north - 7.0).abs() < 0.05, "{}", north);
+        assert!(estimate.position.lat > origin.lat);
+        assert!(estimate.position_accuracy_m > fixed.position_accuracy_m);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::hazard_analyzer::HazardKind;
+use anyhow::{Context, Result, bail};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EarconConfig {
+    pub enabled: bool,
+    // 0.0 - 1.0
+    pub volume: f64,
+    pub sample_rate: u32,
+    // Hazards further away than this stay silent
+    pub max_distance_m: f64,
+}
+
+impl Default for EarconConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume: 0.5,
+            sample_rate: 22050,
+            max_distance_m: 30.0,
+        }
+    }
+}
+
+const BEEP_DURATION: f64 = 0.08;
+const FADE_DURATION: f64 = 0.005;
+
+// How a single alert sounds:
+// - pitch encodes the hazard kind, dropped a fifth when it is behind the user
+// - stereo pan encodes the side (-1 = left, 1 = right)
+// - the number of beeps and the gap between them encode distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pattern {
+    pub frequency: f64,
+    pub pan: f64,
+    pub beeps: u32,
+    pub gap: f64,
+}
+
+impl Pattern {
+    // `relative_angle` uses the same convention as the vibration system:
+    // 0 = ahead, negative = right, positive = left
+    pub fn for_hazard(kind: HazardKind, relative_angle: f64, distance_m: f64) -> Self {
+        let base = match kind {
+            HazardKind::SilentSignals
+            | HazardKind::UnmarkedCrossing
+            | HazardKind::UncontrolledCrossing
+            | HazardKind::CrossingWithoutTactilePaving
+            | HazardKind::Crossing => 880.0,
+            HazardKind::RaisedKerb
+            | HazardKind::MissingKerbRamp
+            | HazardKind::StepsWithoutHandrail
+            | HazardKind::Steps => 660.0,
+            HazardKind::UnevenSurface | HazardKind::NoSidewalk => 440.0,
+            HazardKind::Generic => 550.0,
+        };
+
+        let frequency = if relative_angle.cos() < 0.0 {
+            base * 2.0 / 3.0
+        } else {
+            base
+        };
+
+        let (beeps, gap) = if distance_m < 5.0 {
+            (4, 0.04)
+        } else if distance_m < 10.0 {
+            (3, 0.08)
+        } else if distance_m < 20.0 {
+            (2, 0.12)
+        } else {
+            (1, 0.0)
+        };
+
+        Self {
+            frequency,
+            pan: -relative_angle.sin(),
+            beeps,
+            gap,
+        }
+    }
+
+    // Interleaved stereo, signed 16-bit
+    pub fn render(&self, sample_rate: u32, volume: f64) -> Vec<i16> {
+        let rate = sample_rate as f64;
+        let beep_samples = (BEEP_DURATION * rate) as usize;
+        let gap_samples = (self.gap * rate) as usize;
+        let fade_samples = (FADE_DURATION * rate).max(1.0);
+
+        // Equal-power panning
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+        let left_gain = angle.cos() * volume.clamp(0.0, 1.0);
+        let right_gain = angle.sin() * volume.clamp(0.0, 1.0);
+
+        let mut samples = Vec::new();
+
+        for beep in 0..self.beeps {
+            for i in 0..beep_samples {
+                // Short fade in and out to avoid clicks
+                let envelope = (i as f64 / fade_samples)
+                    .min((beep_samples - i) as f64 / fade_samples)
+                    .min(1.0);
+                let value = (2.0 * PI * self.frequency * i as f64 / rate).sin() * envelope;
+
+                samples.push((value * left_gain * i16::MAX as f64) as i16);
+                samples.push((value * right_gain * i16::MAX as f64) as i16);
+            }
+
+            if beep + 1 < self.beeps {
+                samples.extend(std::iter::repeat_n(0, gap_samples * 2));
+            }
+        }
+
+        samples
+    }
+}
+
+fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let channels: u16 = 2;
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+    out.extend_from_slice(&(channels * 2).to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+
+    for sample in samples {
+        out.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    out
+}
+
+async fn play(wav: Vec<u8>) -> Result<()> {
+    let mut aplay = Command::new("aplay")
+        .args(["-q", "-t", "wav", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start aplay")?;
+
+    let mut stdin = aplay.stdin.take().unwrap();
+    stdin.write_all(&wav).await?;
+    drop(stdin);
+
+    let status = aplay.wait().await?;
+    if !status.success() {
+        bail!("aplay exited with {}", status);
+    }
+
+    Ok(())
+}
+
+// Audio counterpart to the vibration motors. Alerts repeat like a parking
+// sensor, more often the closer the hazard is.
+pub struct Earcons {
+    config: EarconConfig,
+    last_alert: Option<Instant>,
+    playing: Option<AbortHandle>,
+}
+
+impl Earcons {
+    pub fn new(config: EarconConfig) -> Self {
+        Self {
+            config,
+            last_alert: None,
+            playing: None,
+        }
+    }
+
+    pub fn alert(&mut self, kind: HazardKind, relative_angle: f64, distance_m: f64) {
+        if distance_m > self.config.max_distance_m {
+            return;
+        }
+
+        let interval = Duration::from_secs_f64((distance_m / 10.0).clamp(0.5, 3.0));
+        if self.last_alert.is_some_and(|last| last.elapsed() < interval) {
+            return;
+        }
+        self.last_alert = Some(Instant::now());
+
+        let pattern = Pattern::for_hazard(kind, relative_angle, distance_m);
+        let samples = pattern.render(self.config.sample_rate, self.config.volume);
+        let wav = wav(&samples, self.config.sample_rate);
+
+        // A newer alert replaces one that is still playing
+        self.stop();
+        let handle = tokio::spawn(async move {
+            if let Err(e) = play(wav).await {
+                warn!("Earcon failed: {}", e);
+            }
+        });
+        self.playing = Some(handle.abort_handle());
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.playing.take() {
+            handle.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::earcon::Pattern;
+    use crate::hazard_analyzer::HazardKind;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn pattern_encoding() {
+        // Close crossing to the right
+        let right = Pattern::for_hazard(HazardKind::UnmarkedCrossing, -PI / 2.0, 3.0);
+        assert_eq!(right.frequency, 880.0);
+        assert_eq!(right.beeps, 4);
+        assert!((right.pan - 1.0).abs() < 1e-9);
+
+        // Distant kerb behind and to the left
+        let behind = Pattern::for_hazard(HazardKind::RaisedKerb, 3.0 * PI / 4.0, 25.0);
+        assert_eq!(behind.frequency, 440.0);
+        assert_eq!(behind.beeps, 1);
+        assert!(behind.pan < 0.0);
+    }
+
+    #[test]
+    fn render_pans_to_one_side() {
+        let pattern = Pattern {
+            frequency: 440.0,
+            pan: 1.0,
+            beeps: 2,
+            gap: 0.1,
+        };
+
+        let samples = pattern.render(10000, 1.0);
+
+        // Two 80 ms beeps and one 100 ms gap, stereo
+        assert_eq!(samples.len(), (800 * 2 + 1000) * 2);
+
+        let left_peak = samples.iter().step_by(2).map(|s| s.abs()).max().unwrap();
+        let right_peak = samples.iter().skip(1).step_by(2).map(|s| s.abs()).max().unwrap();
+        assert!(left_peak < 10);
+        assert!(right_peak > 30000);
+    }
+}
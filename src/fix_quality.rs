@@ -0,0 +1,229 @@
+use crate::gps::GNRMC;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FixQualityConfig {
+    // A fix above this HDOP or with fewer satellites is degraded
+    pub max_hdop: f64,
+    pub min_satellites: u8,
+    // Time without a valid fix before the position counts as degraded, then lost
+    pub degraded_after_secs: f64,
+    pub lost_after_secs: f64,
+    // A better mode must hold this long before it is announced, so a fix
+    // flickering in and out isn't spoken every second
+    pub recovery_secs: f64,
+    // Motor intensity while degraded, as a fraction of the normal intensity
+    pub degraded_haptic_scale: f64,
+}
+
+impl Default for FixQualityConfig {
+    fn default() -> Self {
+        Self {
+            max_hdop: 2.5,
+            min_satellites: 6,
+            degraded_after_secs: 2.0,
+            lost_after_secs: 5.0,
+            recovery_secs: 3.0,
+            degraded_haptic_scale: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixMode {
+    Good,
+    Degraded,
+    Lost,
+}
+
+impl FixMode {
+    pub fn message_id(&self) -> &'static str {
+        match self {
+            FixMode::Good => "fix.good",
+            FixMode::Degraded => "fix.degraded",
+            FixMode::Lost => "fix.lost",
+        }
+    }
+}
+
+pub struct FixQualityMonitor {
+    config: FixQualityConfig,
+    started: Instant,
+    last_valid: Option<Instant>,
+    mode: FixMode,
+    // Better mode seen since the given time, not yet taken over
+    recovering: Option<(FixMode, Instant)>,
+}
+
+impl FixQualityMonitor {
+    pub fn new(config: FixQualityConfig, now: Instant) -> Self {
+        Self {
+            config,
+            started: now,
+            last_valid: None,
+            mode: FixMode::Good,
+            recovering: None,
+        }
+    }
+
+    pub fn mode(&self) -> FixMode {
+        self.mode
+    }
+
+    // Time since the last valid fix; None before the first
+    pub fn age(&self, now: Instant) -> Option<Duration> {
+        self.last_valid.map(|last| now.duration_since(last))
+    }
+
+    // Returns the new mode when it changed
+    pub fn update(&mut self, fix: &GNRMC, now: Instant) -> Option<FixMode> {
+        if fix.status == 1 {
+            self.last_valid = Some(now);
+        }
+
+        let observed = self.assess_age(now).unwrap_or_else(|| self.assess_fix(fix));
+        self.transition(observed, now)
+    }
+
+    // For when no sentence arrives at all, e.g. an unplugged receiver. Only
+    // the age of the last valid fix is known.
+    pub fn tick(&mut self, now: Instant) -> Option<FixMode> {
+        let observed = self.assess_age(now).unwrap_or(self.mode);
+        self.transition(observed, now)
+    }
+
+    fn transition(&mut self, observed: FixMode, now: Instant) -> Option<FixMode> {
+        if observed >= self.mode {
+            self.recovering = None;
+            if observed == self.mode {
+                return None;
+            }
+        } else {
+            let since = match self.recovering {
+                Some((mode, since)) if mode == observed => since,
+                _ => now,
+            };
+            self.recovering = Some((observed, since));
+            if now.duration_since(since).as_secs_f64() < self.config.recovery_secs {
+                return None;
+            }
+            self.recovering = None;
+        }
+
+        self.mode = observed;
+        Some(observed)
+    }
+
+    fn assess_age(&self, now: Instant) -> Option<FixMode> {
+        let age = now
+            .duration_since(self.last_valid.unwrap_or(self.started))
+            .as_secs_f64();
+        if age > self.config.lost_after_secs {
+            return Some(FixMode::Lost);
+        }
+        if self.last_valid.is_none() || age > self.config.degraded_after_secs {
+            return Some(FixMode::Degraded);
+        }
+
+        None
+    }
+
+    fn assess_fix(&self, fix: &GNRMC) -> FixMode {
+        // Sources other than the GPS module report neither
+        let poor_hdop = fix.hdop.is_some_and(|hdop| hdop > self.config.max_hdop);
+        let few_satellites = fix.satellites > 0 && fix.satellites < self.config.min_satellites;
+        if fix.status != 1 || poor_hdop || few_satellites {
+            FixMode::Degraded
+        } else {
+            FixMode::Good
+        }
+    }
+
+    // Factor for the motor intensities. Direction is still given while
+    // lost as long as dead reckoning keeps the position reliable.
+    pub fn haptic_scale(&self, position_reliable: bool) -> f64 {
+        match self.mode {
+            FixMode::Good => 1.0,
+            FixMode::Lost if !position_reliable => 0.0,
+            FixMode::Degraded | FixMode::Lost => self.config.degraded_haptic_scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fix_quality::{FixMode, FixQualityConfig, FixQualityMonitor};
+    use crate::gps::GNRMC;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn modes_follow_fix_quality() {
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let good = GNRMC {
+            status: 1,
+            satellites: 9,
+            hdop: Some(0.9),
+            ..Default::default()
+        };
+        let mut monitor = FixQualityMonitor::new(FixQualityConfig::default(), start);
+
+        assert_eq!(monitor.update(&good, at(0.0)), None);
+
+        // Few satellites and a high HDOP degrade at once
+        let poor = GNRMC {
+            satellites: 4,
+            hdop: Some(4.0),
+            ..good
+        };
+        assert_eq!(monitor.update(&poor, at(1.0)), Some(FixMode::Degraded));
+        assert_eq!(monitor.haptic_scale(true), 0.5);
+
+        // No fix at all for longer than `lost_after_secs`
+        let none = GNRMC::default();
+        for secs in 2..=7 {
+            monitor.update(&none, at(secs as f64));
+        }
+        assert_eq!(monitor.mode(), FixMode::Lost);
+        assert_eq!(monitor.haptic_scale(false), 0.0);
+        assert_eq!(monitor.age(at(7.0)), Some(Duration::from_secs(6)));
+
+        // Recovery is only taken after it held for `recovery_secs`
+        assert_eq!(monitor.update(&good, at(8.0)), None);
+        assert_eq!(monitor.update(&good, at(10.0)), None);
+        assert_eq!(monitor.update(&good, at(11.0)), Some(FixMode::Good));
+        assert_eq!(monitor.haptic_scale(false), 1.0);
+    }
+
+    #[test]
+    fn silence_is_lost() {
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let mut monitor = FixQualityMonitor::new(FixQualityConfig::default(), start);
+
+        // Nothing arrives at all from the start
+        assert_eq!(monitor.tick(at(1.0)), Some(FixMode::Degraded));
+        assert_eq!(monitor.tick(at(5.0)), None);
+        assert_eq!(monitor.tick(at(6.0)), Some(FixMode::Lost));
+        assert_eq!(monitor.age(at(6.0)), None);
+
+        // A good fix, then the receiver goes quiet
+        let good = GNRMC {
+            status: 1,
+            ..Default::default()
+        };
+        for secs in 7..=10 {
+            monitor.update(&good, at(secs as f64));
+        }
+        assert_eq!(monitor.mode(), FixMode::Good);
+        assert_eq!(monitor.tick(at(11.0)), None);
+        assert_eq!(monitor.tick(at(12.5)), Some(FixMode::Degraded));
+        assert_eq!(monitor.tick(at(15.5)), Some(FixMode::Lost));
+        assert_eq!(monitor.haptic_scale(false), 0.0);
+    }
+}
@@ -1,104 +1,203 @@
+use crate::networking::Metrics;
 use crate::overpass::Point;
-use anyhow::Result;
-use rppal::uart::{Parity, Uart};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rppal::uart::{Parity, Queue, Uart};
 use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
 use std::time::Duration;
-use log::info;
-use tokio::time::sleep;
+use log::{info, warn};
+use tokio::time::{Instant, sleep};
 
 pub struct Gps {
     uart: Uart,
     buffer: Vec<u8>,
+    decoder: NmeaDecoder,
+    fix_interval: Duration,
 }
 
+// Turns a stream of NMEA sentences into fixes. Shared by the UART reader and
+// NMEA log replay.
+#[derive(Default)]
+pub struct NmeaDecoder {
+    satellites: u8,
+    hdop: Option<f64>,
+}
+
+// Baud rates the module supports, most likely first when probing
+const BAUD_RATES: [u32; 7] = [9600, 115200, 57600, 38400, 19200, 14400, 4800];
+
+const ACK_TIMEOUT: Duration = Duration::from_millis(1000);
+const ACK_ATTEMPTS: u32 = 3;
+// Short, since most rates being probed are wrong
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GpsOptions {
+    pub path: String,
+    // Negotiated at startup from whatever rate the module is at. 9600 is too
+    // slow for RMC and GGA at 10 Hz.
+    pub baud_rate: u32,
+    pub fix_interval_ms: u32,
+    pub output: NmeaOutput,
+    pub power_mode: PowerMode,
+}
+
+impl Default for GpsOptions {
+    fn default() -> Self {
+        Self {
+            path: "/dev/ttyS0".to_string(),
+            baud_rate: 115200,
+            fix_interval_ms: 100,
+            output: NmeaOutput::default(),
+            power_mode: PowerMode::Normal,
+        }
+    }
+}
+
+// Sentences the module sends, each every nth fix; 0 turns one off. Only RMC
+// and GGA are used.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NmeaOutput {
+    pub gll: u8,
+    pub rmc: u8,
+    pub vtg: u8,
+    pub gga: u8,
+    pub gsa: u8,
+    pub gsv: u8,
+    pub zda: u8,
+}
+
+impl Default for NmeaOutput {
+    fn default() -> Self {
+        Self {
+            gll: 0,
+            rmc: 1,
+            vtg: 0,
+            gga: 1,
+            gsa: 0,
+            gsv: 0,
+            zda: 0,
+        }
+    }
+}
+
+impl NmeaOutput {
+    // Rough bytes per second at the given fix interval, to check the baud rate
+    fn bytes_per_second(&self, fix_interval_ms: u32) -> f64 {
+        let sentences = [self.gll, self.rmc, self.vtg, self.gga, self.gsa, self.zda]
+            .iter()
+            .filter(|rate| **rate > 0)
+            .map(|rate| 1.0 / *rate as f64)
+            .sum::<f64>()
+            // GSV comes as up to four sentences
+            + if self.gsv > 0 { 4.0 / self.gsv as f64 } else { 0.0 };
+
+        sentences * 80.0 * 1000.0 / fix_interval_ms.max(1) as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PowerMode {
+    #[default]
+    Normal,
+    // Tracks for `run_ms`, then sleeps for `sleep_ms`, keeping ephemeris in
+    // backup (lowest power) or standby (faster to resume)
+    PeriodicBackup { run_ms: u32, sleep_ms: u32 },
+    PeriodicStandby { run_ms: u32, sleep_ms: u32 },
+    // Lets the module pick the rate from how much the position changes
+    AlwaysLocateStandby,
+    AlwaysLocateBackup,
+}
+
+// PMTK packets of the MediaTek GNSS chipset
+#[derive(Debug, Clone, Copy)]
 pub enum Command {
-    HotStart,
-    WarmStart,
-    ColdStart,
-    FullColdStart,
-    SetPerpetualStandbyMode,
-    SetPeriodicMode,
-    SetNormalMode,
-    SetPeriodicBackupMode,
-    SetPeriodicStandbyMode,
-    SetPerpetualBackupMode,
-    SetAlwaysLocateStandbyMode,
-    SetAlwaysLocateBackupMode,
-    SetPosFix,
-    SetPosFix100ms,
-    SetPosFix200ms,
-    SetPosFix400ms,
-    SetPosFix800ms,
-    SetPosFix1s,
-    SetPosFix2s,
-    SetPosFix4s,
-    SetPosFix8s,
-    SetPosFix10s,
-    SetSyncPpsNmeaOff,
-    SetSyncPpsNmeaOn,
-    SetNmeaBaudrate,
-    SetNmeaBaudrate115200,
-    SetNmeaBaudrate57600,
-    SetNmeaBaudrate38400,
-    SetNmeaBaudrate19200,
-    SetNmeaBaudrate14400,
-    SetNmeaBaudrate9600,
-    SetNmeaBaudrate4800,
-    SetReduction,
-    SetNmeaOutput,
+    // Does nothing but is acknowledged; finds the baud rate
+    Test,
+    SetFixInterval { ms: u32 },
+    SetBaudRate(u32),
+    SetNmeaOutput(NmeaOutput),
+    SetPowerMode(PowerMode),
 }
 
 impl Command {
-    pub fn as_str(&self) -> &'static str {
+    pub fn packet_type(&self) -> u16 {
         match self {
-            Command::HotStart => "$PMTK101",
-            Command::WarmStart => "$PMTK102",
-            Command::ColdStart => "$PMTK103",
-            Command::FullColdStart => "$PMTK104",
-            Command::SetPerpetualStandbyMode => "$PMTK161",
-            Command::SetPeriodicMode => "$PMTK225",
-            Command::SetNormalMode => "$PMTK225,0",
-            Command::SetPeriodicBackupMode => "$PMTK225,1,1000,2000",
-            Command::SetPeriodicStandbyMode => "$PMTK225,2,1000,2000",
-            Command::SetPerpetualBackupMode => "$PMTK225,4",
-            Command::SetAlwaysLocateStandbyMode => "$PMTK225,8",
-            Command::SetAlwaysLocateBackupMode => "$PMTK225,9",
-            Command::SetPosFix => "$PMTK220",
-            Command::SetPosFix100ms => "$PMTK220,100",
-            Command::SetPosFix200ms => "$PMTK220,200",
-            Command::SetPosFix400ms => "$PMTK220,400",
-            Command::SetPosFix800ms => "$PMTK220,800",
-            Command::SetPosFix1s => "$PMTK220,1000",
-            Command::SetPosFix2s => "$PMTK220,2000",
-            Command::SetPosFix4s => "$PMTK220,4000",
-            Command::SetPosFix8s => "$PMTK220,8000",
-            Command::SetPosFix10s => "$PMTK220,10000",
-            Command::SetSyncPpsNmeaOff => "$PMTK255,0",
-            Command::SetSyncPpsNmeaOn => "$PMTK255,1",
-            Command::SetNmeaBaudrate => "$PMTK251",
-            Command::SetNmeaBaudrate115200 => "$PMTK251,115200",
-            Command::SetNmeaBaudrate57600 => "$PMTK251,57600",
-            Command::SetNmeaBaudrate38400 => "$PMTK251,38400",
-            Command::SetNmeaBaudrate19200 => "$PMTK251,19200",
-            Command::SetNmeaBaudrate14400 => "$PMTK251,14400",
-            Command::SetNmeaBaudrate9600 => "$PMTK251,9600",
-            Command::SetNmeaBaudrate4800 => "$PMTK251,4800",
-            Command::SetReduction => "$PMTK314,-1",
-            Command::SetNmeaOutput => "$PMTK314,1,1,1,1,1,1,1,1,0,0,0,0,0,0,0,0,0,1,0",
+            Command::Test => 0,
+            Command::SetFixInterval { .. } => 220,
+            Command::SetPowerMode(_) => 225,
+            Command::SetBaudRate(_) => 251,
+            Command::SetNmeaOutput(_) => 314,
+        }
+    }
+
+    fn data(&self) -> Vec<u32> {
+        match *self {
+            Command::Test => vec![],
+            Command::SetFixInterval { ms } => vec![ms],
+            Command::SetBaudRate(rate) => vec![rate],
+            Command::SetNmeaOutput(output) => {
+                let mut fields = vec![0; 19];
+                fields[0] = output.gll;
+                fields[1] = output.rmc;
+                fields[2] = output.vtg;
+                fields[3] = output.gga;
+                fields[4] = output.gsa;
+                fields[5] = output.gsv;
+                fields[17] = output.zda;
+                fields.into_iter().map(u32::from).collect()
+            }
+            Command::SetPowerMode(mode) => match mode {
+                PowerMode::Normal => vec![0],
+                PowerMode::PeriodicBackup { run_ms, sleep_ms } => vec![1, run_ms, sleep_ms],
+                PowerMode::PeriodicStandby { run_ms, sleep_ms } => vec![2, run_ms, sleep_ms],
+                PowerMode::AlwaysLocateStandby => vec![8],
+                PowerMode::AlwaysLocateBackup => vec![9],
+            },
         }
     }
+
+    // A baud rate change takes effect before the ack could be sent
+    fn expects_ack(&self) -> bool {
+        !matches!(self, Command::SetBaudRate(_))
+    }
+
+    // The full sentence, e.g. "$PMTK220,100*2F\r\n"
+    pub fn sentence(&self) -> String {
+        let mut body = format!("PMTK{:03}", self.packet_type());
+        for field in self.data() {
+            body.push_str(&format!(",{}", field));
+        }
+        let checksum = body.bytes().fold(0u8, |acc, b| acc ^ b);
+
+        format!("${}*{:02X}\r\n", body, checksum)
+    }
+}
+
+// Flag of a $PMTK001 acknowledgement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckResult {
+    Invalid,
+    Unsupported,
+    Failed,
+    Succeeded,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
 pub struct GNRMC {
     pub lon: f64,
     pub lat: f64,
     pub lon_area: u8,
     pub lat_area: u8,
-    pub time_h: u8,
-    pub time_m: u8,
-    pub time_s: u8,
+    // UTC date and time of the fix; None until the receiver knows the date
+    pub time: Option<DateTime<Utc>>,
     pub status: u8, // 1:Successful positioning 0：Positioning failed
+    pub satellites: u8, // From the last GGA sentence
+    pub hdop: Option<f64>,
 }
 
 impl Default for GNRMC {
@@ -108,10 +207,10 @@ impl Default for GNRMC {
             lat: 0.0,
             lon_area: 0,
             lat_area: 0,
-            time_h: 0,
-            time_m: 0,
-            time_s: 0,
+            time: None,
             status: 0,
+            satellites: 0,
+            hdop: None,
         }
     }
 }
@@ -135,6 +234,24 @@ impl GNRMC {
 
         Point { lat, lon }
     }
+
+    // Valid fix at a position in decimal degrees, for sources other than the
+    // GPS module
+    pub fn from_point(point: Point) -> Self {
+        let to_nmea = |degrees: f64| {
+            let degrees = degrees.abs();
+            degrees.trunc() * 100.0 + degrees.fract() * 60.0
+        };
+
+        GNRMC {
+            lat: to_nmea(point.lat),
+            lon: to_nmea(point.lon),
+            lat_area: if point.lat < 0.0 { b'S' } else { b'N' },
+            lon_area: if point.lon < 0.0 { b'W' } else { b'E' },
+            status: 1,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -156,266 +273,414 @@ impl Vector {
     pub fn new(rotation: f64, length: f64) -> Self {
         Self { rotation, length }
     }
+
+    // Re-expresses an absolute bearing relative to the user's heading,
+    // normalized to [-π, π]: 0 = straight ahead, negative = right, positive = left
+    pub fn relative_to(&self, heading: f64) -> Vector {
+        let mut relative_angle = self.rotation - heading;
+
+        while relative_angle > PI {
+            relative_angle -= 2.0 * PI;
+        }
+        while relative_angle < -PI {
+            relative_angle += 2.0 * PI;
+        }
+
+        Vector::new(relative_angle, self.length)
+    }
 }
 
 impl Gps {
-    pub fn new() -> Self {
-        let mut uart = Uart::with_path("/dev/ttyS0", 9600, Parity::None, 8, 1).unwrap();
+    pub fn new(options: &GpsOptions) -> Result<Self> {
+        // The module starts at 9600 unless a backup battery kept another rate
+        let uart = Uart::with_path(&options.path, 9600, Parity::None, 8, 1)
+            .with_context(|| format!("Failed to open {}", options.path))?;
 
-        Self {
+        Ok(Self {
             uart,
             buffer: Vec::new(),
-        }
+            decoder: NmeaDecoder::default(),
+            fix_interval: Duration::from_millis(options.fix_interval_ms.into()),
+        })
     }
 
-    pub async fn init(&mut self) {
-        // sleep(Duration::from_millis(250)).await;
-        // self.send_command(Command::SetNmeaBaudrate115200)
-        //     .await
-        //     .unwrap();
-        // sleep(Duration::from_millis(250)).await;
-        //
-        // self.set_baud_rate(115200).unwrap();
-        // sleep(Duration::from_millis(100)).await;
-
-        self.send_command(Command::SetPosFix100ms).await.unwrap();
-        self.send_command(Command::SetNmeaOutput).await.unwrap();
+    pub fn fix_interval(&self) -> Duration {
+        self.fix_interval
     }
 
-    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
-        self.uart.set_baud_rate(baud_rate)?;
+    pub async fn init(&mut self, options: &GpsOptions) -> Result<()> {
+        self.negotiate_baud_rate(options.baud_rate).await?;
+
+        // 10 bits on the wire per byte
+        let capacity = self.uart.baud_rate() as f64 / 10.0;
+        if options.output.bytes_per_second(options.fix_interval_ms) > capacity {
+            warn!(
+                "{} baud is too slow for the NMEA output every {} ms; sentences will be dropped",
+                self.uart.baud_rate(),
+                options.fix_interval_ms
+            );
+        }
+
+        // Cut the output down before speeding up the fixes
+        self.send_command(Command::SetNmeaOutput(options.output)).await?;
+        self.send_command(Command::SetFixInterval {
+            ms: options.fix_interval_ms,
+        })
+        .await?;
+        self.send_command(Command::SetPowerMode(options.power_mode)).await?;
 
         Ok(())
     }
 
-    pub async fn send_command(&mut self, command: Command) -> Result<()> {
-        let cmd_str = command.as_str();
-        let mut checksum: u8 = 0;
-        for byte in cmd_str.as_bytes().iter().skip(1) {
-            checksum ^= *byte;
+    // Finds the rate the module talks at, then switches both ends to
+    // `target`. Stays at the found rate if the module goes quiet after the
+    // switch.
+    async fn negotiate_baud_rate(&mut self, target: u32) -> Result<()> {
+        let candidates =
+            std::iter::once(target).chain(BAUD_RATES.into_iter().filter(|rate| *rate != target));
+
+        let mut current = None;
+        for rate in candidates {
+            self.uart.set_baud_rate(rate)?;
+            if self.probe().await {
+                current = Some(rate);
+                break;
+            }
         }
+        let Some(current) = current else {
+            bail!("GPS module doesn't answer at any baud rate");
+        };
+        info!("GPS module answers at {} baud", current);
 
-        let full_command = format!("{}*{:02X}\r\n", cmd_str, checksum);
-        self.uart.write(full_command.as_bytes())?;
+        if current == target {
+            return Ok(());
+        }
+
+        self.send_command(Command::SetBaudRate(target)).await?;
+        self.uart.set_baud_rate(target)?;
+        for _ in 0..ACK_ATTEMPTS {
+            if self.probe().await {
+                info!("GPS switched to {} baud", target);
+                return Ok(());
+            }
+        }
 
-        sleep(Duration::from_millis(200)).await;
+        warn!("GPS module doesn't answer at {} baud, staying at {}", target, current);
+        self.uart.set_baud_rate(current)?;
 
         Ok(())
     }
 
-    pub async fn get(&mut self) -> GNRMC {
-        let mut attempt = 0;
-        const MAX_ATTEMPTS: u32 = 5;
+    // Whether the module acknowledges a test packet at the current baud rate
+    async fn probe(&mut self) -> bool {
+        // Whatever was read at the previous rate is garbage
+        self.buffer.clear();
+        let _ = self.uart.flush(Queue::Input);
 
-        loop {
-            attempt += 1;
+        if self.uart.write(Command::Test.sentence().as_bytes()).is_err() {
+            return false;
+        }
+
+        self.wait_for_ack(Command::Test.packet_type(), PROBE_TIMEOUT).await
+            == Some(AckResult::Succeeded)
+    }
 
-            let mut buff_t = vec![0u8; 800];
-            let bytes_read = self.uart.read(&mut buff_t).unwrap_or(0);
+    // Sends a command and waits for its $PMTK001 acknowledgement, resending
+    // when none comes or the module reports a failure
+    pub async fn send_command(&mut self, command: Command) -> Result<()> {
+        for attempt in 1..=ACK_ATTEMPTS {
+            self.uart.write(command.sentence().as_bytes())?;
 
-            if bytes_read > 0 {
-                self.buffer.extend_from_slice(&buff_t[..bytes_read]);
+            if !command.expects_ack() {
+                // Nothing to wait for; give the module time to act on it
+                sleep(Duration::from_millis(200)).await;
+                return Ok(());
             }
 
-            // println!("{}", String::from_utf8_lossy(&self.buffer));
-
-            let mut pos = 0;
-            while pos < self.buffer.len() {
-                if self.buffer[pos] == b'$' {
-                    let mut end = pos + 1;
-                    while end < self.buffer.len()
-                        && self.buffer[end] != b'\r'
-                        && self.buffer[end] != b'\n'
-                        && self.buffer[end] != b'$'
-                    {
-                        end += 1;
-                    }
-
-                    if end < self.buffer.len()
-                        && (self.buffer[end] == b'\r' || self.buffer[end] == b'\n')
-                    {
-                        let sentence_bytes = &self.buffer[pos..end];
-                        let sentence_str = String::from_utf8_lossy(sentence_bytes);
-
-                        if sentence_bytes.len() > 6
-                            && sentence_bytes[1] == b'G'
-                            && (sentence_bytes[2] == b'N' || sentence_bytes[2] == b'P')
-                            && sentence_bytes[3] == b'R'
-                            && sentence_bytes[4] == b'M'
-                            && sentence_bytes[5] == b'C'
-                        {
-                            let parts: Vec<&str> = sentence_str.split(',').collect();
-
-                            let mut gps = GNRMC::default();
-
-                            if parts.len() >= 3 {
-                                if parts.len() > 1 && parts[1].len() >= 6 {
-                                    let time_str = &parts[1][..6];
-                                    if let Ok(time) = time_str.parse::<u32>() {
-                                        gps.time_h = ((time / 10000 + 8) % 24) as u8;
-                                        gps.time_m = ((time / 100) % 100) as u8;
-                                        gps.time_s = (time % 100) as u8;
-                                    }
-                                }
-
-                                let status_str = if parts.len() > 2 { parts[2].trim() } else { "" };
-                                gps.status = if status_str == "A" { 1 } else { 0 };
-
-                                if gps.status == 1 {
-                                    if parts.len() > 3 && !parts[3].is_empty() {
-                                        if let Ok(lat) = parts[3].parse::<f64>() {
-                                            gps.lat = lat;
-                                        }
-                                    }
-
-                                    if parts.len() > 4 && !parts[4].is_empty() {
-                                        gps.lat_area = parts[4].as_bytes()[0];
-                                    }
-
-                                    if parts.len() > 5 && !parts[5].is_empty() {
-                                        if let Ok(lon) = parts[5].parse::<f64>() {
-                                            gps.lon = lon;
-                                        }
-                                    }
-
-                                    if parts.len() > 6 && !parts[6].is_empty() {
-                                        gps.lon_area = parts[6].as_bytes()[0];
-                                    }
-                                    info!("GPS FIX: lat={:.6}, lon={:.6}", gps.lat, gps.lon);
-                                }
-                            }
-
-                            let clear_until = if end + 1 < self.buffer.len() {
-                                end + 1
-                            } else {
-                                end
-                            };
-                            self.buffer.drain(0..clear_until);
-
-                            return gps;
-                        } else {
-                            let clear_until = if end + 1 < self.buffer.len() {
-                                end + 1
-                            } else {
-                                end
-                            };
-                            self.buffer.drain(0..clear_until);
-                        }
-                    } else {
-                        break;
-                    }
-                } else {
-                    pos += 1;
-                }
+            match self.wait_for_ack(command.packet_type(), ACK_TIMEOUT).await {
+                Some(AckResult::Succeeded) => return Ok(()),
+                Some(AckResult::Failed) | None => warn!(
+                    "GPS didn't acknowledge {:?} (attempt {}/{})",
+                    command, attempt, ACK_ATTEMPTS
+                ),
+                Some(result) => bail!("GPS rejected {:?}: {:?}", command, result),
             }
+        }
+
+        bail!("GPS didn't acknowledge {:?}", command)
+    }
+
+    // Reads until the ack for `packet_type` arrives. Fixes in between are
+    // dropped; nothing uses them while the module is being configured.
+    async fn wait_for_ack(&mut self, packet_type: u16, timeout: Duration) -> Option<AckResult> {
+        let deadline = Instant::now() + timeout;
 
-            if attempt >= MAX_ATTEMPTS {
-                if self.buffer.len() > 2000 {
-                    self.buffer.clear();
+        while Instant::now() < deadline {
+            self.read_available();
+
+            while let Some(sentence) = self.next_sentence() {
+                let ack = verify_checksum(&sentence)
+                    .and_then(parse_ack)
+                    .filter(|(acked, _)| *acked == packet_type);
+                if let Some((_, result)) = ack {
+                    return Some(result);
                 }
             }
 
             sleep(Duration::from_millis(10)).await;
         }
-    }
 
-    // Calculate bearing in radians to determine direction based off movement from 2 points
-    pub fn calculate_bearing(from: &Point, to: &Point) -> f64 {
-        let x = to.lon - from.lon;
-        let y = to.lat - from.lat;
-        let bearing = y.atan2(x);
+        None
+    }
 
-        // println!("Bearing calc: from({:.6}, {:.6}) to ({:.6}, {:.6})", from.lat, from.lon, to.lat, to.lon);
-        // println!("  dx={:.8}, dy={:.8}, bearing={:.4} rad ({:.1}°)", x, y, bearing, bearing.to_degrees());
+    fn read_available(&mut self) {
+        let mut buff_t = vec![0u8; 800];
+        let bytes_read = self.uart.read(&mut buff_t).unwrap_or(0);
 
-        bearing
+        if bytes_read > 0 {
+            self.buffer.extend_from_slice(&buff_t[..bytes_read]);
+        }
     }
 
-    pub async fn get_with_direction(
-        &mut self,
-        previous_position: Option<Point>,
-    ) -> (GNRMC, Option<f64>) {
-        let current_reading = self.get().await;
+    pub async fn get(&mut self) -> GNRMC {
+        let mut attempt = 0;
+        const MAX_ATTEMPTS: u32 = 5;
+
+        loop {
+            attempt += 1;
+
+            self.read_available();
 
-        if current_reading.status == 1 {
-            let current_position = current_reading.google_coordinates();
+            while let Some(sentence) = self.next_sentence() {
+                if let Some(fix) = self.decoder.handle_sentence(&sentence) {
+                    return fix;
+                }
+            }
 
-            if let Some(prev_pos) = previous_position {
-                let direction = Self::calculate_bearing(&prev_pos, &current_position);
-                return (current_reading, Some(direction));
+            if attempt >= MAX_ATTEMPTS && self.buffer.len() > 2000 {
+                self.buffer.clear();
             }
+
+            sleep(Duration::from_millis(10)).await;
         }
+    }
+
+    // Takes the next complete "$...\r\n" sentence out of the buffer, without
+    // the line ending. Incomplete sentences are left for the next read.
+    fn next_sentence(&mut self) -> Option<String> {
+        loop {
+            let start = self.buffer.iter().position(|b| *b == b'$')?;
+            self.buffer.drain(..start);
+
+            let end = self.buffer[1..]
+                .iter()
+                .position(|b| matches!(b, b'\r' | b'\n' | b'$'))?
+                + 1;
+
+            if self.buffer[end] == b'$' {
+                // Truncated sentence, drop it and resync on the next one
+                Metrics::nmea_parse_error();
+                self.buffer.drain(..end);
+                continue;
+            }
+
+            let sentence = String::from_utf8_lossy(&self.buffer[..end]).to_string();
+            self.buffer.drain(..=end);
 
-        (current_reading, None)
+            return Some(sentence);
+        }
     }
 }
 
-pub struct GpsSimulator {
-    starting_point: Point,
-    ending_point: Point,
-    current_point: Point,
-}
+impl NmeaDecoder {
+    // Updates satellite/HDOP state from GGA and returns a fix for RMC sentences
+    pub fn handle_sentence(&mut self, sentence: &str) -> Option<GNRMC> {
+        let Some(body) = verify_checksum(sentence) else {
+            Metrics::nmea_parse_error();
+            warn!("Dropping NMEA sentence with bad checksum: {}", sentence);
+            return None;
+        };
+
+        match sentence_type(body) {
+            Some("GGA") => {
+                match parse_gga(body) {
+                    Some((satellites, hdop)) => {
+                        self.satellites = satellites;
+                        self.hdop = hdop;
+                    }
+                    None => Metrics::nmea_parse_error(),
+                }
+                None
+            }
+            Some("RMC") => {
+                let Some(mut gps) = parse_rmc(body) else {
+                    Metrics::nmea_parse_error();
+                    return None;
+                };
+
+                gps.satellites = self.satellites;
+                gps.hdop = self.hdop;
+
+                Metrics::gps_fix(gps.status == 1, gps.satellites);
+                if gps.status == 1 {
+                    info!("GPS FIX: lat={:.6}, lon={:.6}", gps.lat, gps.lon);
+                }
 
-impl GpsSimulator {
-    pub fn new(starting_point: Point, ending_point: Point) -> Self {
-        Self {
-            starting_point,
-            ending_point,
-            current_point: starting_point,
+                Some(gps)
+            }
+            _ => None,
         }
     }
+}
 
-    pub fn get(&mut self) -> Option<Point> {
-        let step_size = 0.00001;
+// Returns the sentence without its "*hh" checksum if the checksum matches.
+// Sentences without a checksum are accepted as-is.
+pub fn verify_checksum(sentence: &str) -> Option<&str> {
+    let sentence = sentence.trim_end();
 
-        let mut next_lat = self.current_point.lat;
-        let mut next_lon = self.current_point.lon;
+    let Some((body, checksum)) = sentence.rsplit_once('*') else {
+        return Some(sentence);
+    };
 
-        // println!("{} {}", (next_lat - self.ending_point.lat).abs(), (next_lon - self.ending_point.lon).abs());
+    let expected = u8::from_str_radix(checksum, 16).ok()?;
+    let actual = body.bytes().skip(1).fold(0u8, |acc, b| acc ^ b);
 
-        if (next_lat - self.ending_point.lat).abs() > step_size
-            || (next_lon - self.ending_point.lon).abs() > step_size
-        {
-            if next_lat < self.ending_point.lat {
-                next_lat += step_size;
-            } else if next_lat > self.ending_point.lat {
-                next_lat -= step_size;
-            }
+    (actual == expected).then_some(body)
+}
 
-            if next_lon < self.ending_point.lon {
-                next_lon += step_size;
-            } else if next_lon > self.ending_point.lon {
-                next_lon -= step_size;
-            }
+// "GGA" for "$GNGGA,...", "RMC" for "$GPRMC,..." and so on
+fn sentence_type(body: &str) -> Option<&str> {
+    let address = body.strip_prefix('$')?.split(',').next()?;
 
-            self.current_point = Point {
-                lat: next_lat,
-                lon: next_lon,
-            };
+    (address.len() == 5 && address.starts_with('G')).then(|| &address[2..])
+}
 
-            Some(self.current_point)
-        } else {
-            None
-        }
+// Parses an RMC sentence (without checksum), e.g.
+// $GNRMC,123519.00,A,4807.038,N,01131.000,E,0.0,0.0,230394,,,A
+pub fn parse_rmc(body: &str) -> Option<GNRMC> {
+    let parts: Vec<&str> = body.split(',').collect();
+
+    if parts.len() < 3 {
+        return None;
     }
 
-    pub fn get_with_direction(
-        &mut self,
-        previous_position: Option<Point>,
-    ) -> (Option<Point>, Option<f64>) {
-        let current_position = self.get();
+    let mut gps = GNRMC {
+        time: parse_datetime(parts[1], parts.get(9).copied().unwrap_or_default()),
+        ..Default::default()
+    };
+
+    gps.status = if parts[2].trim() == "A" { 1 } else { 0 };
 
-        if current_position.is_none() {
-            return (None, None);
+    if gps.status == 1 {
+        if let Some(lat) = parts.get(3).and_then(|p| p.parse::<f64>().ok()) {
+            gps.lat = lat;
         }
 
-        let current_pos = current_position.unwrap();
+        if let Some(area) = parts.get(4).and_then(|p| p.bytes().next()) {
+            gps.lat_area = area;
+        }
 
-        if let Some(prev_pos) = previous_position {
-            let direction = Gps::calculate_bearing(&prev_pos, &current_pos);
-            return (Some(current_pos), Some(direction));
+        if let Some(lon) = parts.get(5).and_then(|p| p.parse::<f64>().ok()) {
+            gps.lon = lon;
         }
 
-        (Some(current_pos), None)
+        if let Some(area) = parts.get(6).and_then(|p| p.bytes().next()) {
+            gps.lon_area = area;
+        }
+    }
+
+    Some(gps)
+}
+
+// Combines RMC time "hhmmss.ss" and date "ddmmyy", both UTC. Either is empty
+// before the receiver has heard from a satellite.
+fn parse_datetime(time: &str, date: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(date, "%d%m%y").ok()?;
+    let time = NaiveTime::parse_from_str(time, "%H%M%S%.f").ok()?;
+
+    Some(date.and_time(time).and_utc())
+}
+
+// Parses a "$PMTK001,<command>,<flag>" acknowledgement (without checksum)
+pub fn parse_ack(body: &str) -> Option<(u16, AckResult)> {
+    let mut parts = body.strip_prefix("$PMTK001,")?.split(',');
+    let command = parts.next()?.parse().ok()?;
+    let result = match parts.next()? {
+        "0" => AckResult::Invalid,
+        "1" => AckResult::Unsupported,
+        "2" => AckResult::Failed,
+        "3" => AckResult::Succeeded,
+        _ => return None,
+    };
+
+    Some((command, result))
+}
+
+// Parses satellites in use and HDOP from a GGA sentence (without checksum)
+pub fn parse_gga(body: &str) -> Option<(u8, Option<f64>)> {
+    let parts: Vec<&str> = body.split(',').collect();
+
+    let satellites = parts.get(7)?;
+    let satellites = if satellites.is_empty() {
+        0
+    } else {
+        satellites.parse().ok()?
+    };
+    let hdop = parts.get(8).and_then(|p| p.parse::<f64>().ok());
+
+    Some((satellites, hdop))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gps::{
+        AckResult, Command, NmeaOutput, PowerMode, parse_ack, parse_gga, parse_rmc,
+        verify_checksum,
+    };
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn parse_sentences() {
+        let gga = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        let body = verify_checksum(gga).unwrap();
+        assert_eq!(parse_gga(body), Some((8, Some(0.9))));
+
+        let rmc = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A";
+        let fix = parse_rmc(verify_checksum(rmc).unwrap()).unwrap();
+        assert_eq!(fix.status, 1);
+        assert_eq!(fix.lat, 4807.038);
+        assert_eq!(fix.lon_area, b'E');
+        assert_eq!(fix.time, Utc.with_ymd_and_hms(1994, 3, 23, 12, 35, 19).single());
+
+        let rmc = "$GNRMC,235959.50,A,4807.038,N,01131.000,E,0.0,0.0,311225,,,A";
+        let time = parse_rmc(rmc).unwrap().time.unwrap();
+        assert_eq!(time.to_rfc3339(), "2025-12-31T23:59:59.500+00:00");
+        assert!(parse_rmc("$GNRMC,,V,,,,,,,,,,N").unwrap().time.is_none());
+
+        assert!(verify_checksum("$GPRMC,123519,A,4807.038,N*00").is_none());
+    }
+
+    #[test]
+    fn pmtk_commands() {
+        assert_eq!(Command::SetFixInterval { ms: 100 }.sentence(), "$PMTK220,100*2F\r\n");
+        assert_eq!(Command::SetBaudRate(115200).sentence(), "$PMTK251,115200*1F\r\n");
+        assert_eq!(
+            Command::SetNmeaOutput(NmeaOutput::default()).sentence(),
+            "$PMTK314,0,1,0,1,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0*28\r\n"
+        );
+        assert!(
+            Command::SetPowerMode(PowerMode::PeriodicStandby {
+                run_ms: 1000,
+                sleep_ms: 2000
+            })
+            .sentence()
+            .starts_with("$PMTK225,2,1000,2000*")
+        );
+        assert_eq!(Command::Test.sentence(), "$PMTK000*32\r\n");
+
+        let ack = verify_checksum("$PMTK001,220,3*30").unwrap();
+        assert_eq!(parse_ack(ack), Some((220, AckResult::Succeeded)));
+        assert_eq!(parse_ack("$PMTK001,314,1"), Some((314, AckResult::Unsupported)));
+        assert_eq!(parse_ack("$GPRMC,123519,A"), None);
     }
 }
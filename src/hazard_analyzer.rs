@@ -1,11 +1,13 @@
 use crate::gps::Vector;
 use crate::overpass::{Element, Point};
+use log::debug;
 use serde::{Deserialize, Serialize};
 
 pub struct HazardAnalyzer {
     lat: f64,
     lon: f64,
     elements: Vec<Element>,
+    profile: HazardProfile,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -15,17 +17,131 @@ pub enum HazardSeverity {
     High,
 }
 
+// Mirrors the categories queried from Overpass in `overpass::fetch`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum HazardKind {
+    SilentSignals,
+    UnmarkedCrossing,
+    UncontrolledCrossing,
+    CrossingWithoutTactilePaving,
+    Crossing,
+    RaisedKerb,
+    MissingKerbRamp,
+    UnevenSurface,
+    NoSidewalk,
+    StepsWithoutHandrail,
+    Steps,
+    Generic,
+}
+
+impl HazardKind {
+    pub fn classify(element: &Element) -> Self {
+        let tags = element.tags();
+        let tag = |key: &str| tags.get(key).map(|v| v.as_str());
+
+        match tag("highway") {
+            Some("traffic_signals") => return HazardKind::SilentSignals,
+            Some("crossing") => {
+                return match (tag("crossing"), tag("tactile_paving")) {
+                    (Some("unmarked"), _) => HazardKind::UnmarkedCrossing,
+                    (Some("uncontrolled"), _) => HazardKind::UncontrolledCrossing,
+                    (_, Some("no" | "incorrect")) => HazardKind::CrossingWithoutTactilePaving,
+                    _ => HazardKind::Crossing,
+                };
+            }
+            Some("steps") => {
+                return match tag("handrail") {
+                    None | Some("no") => HazardKind::StepsWithoutHandrail,
+                    _ => HazardKind::Steps,
+                };
+            }
+            Some("footway" | "sidewalk" | "path" | "pedestrian")
+                if matches!(
+                    tag("surface"),
+                    Some(
+                        "unpaved" | "gravel" | "dirt" | "sand" | "ground" | "cobblestone"
+                            | "pebblestone" | "grass"
+                    )
+                ) =>
+            {
+                return HazardKind::UnevenSurface;
+            }
+            Some("primary" | "secondary" | "tertiary" | "residential")
+                if matches!(tag("sidewalk"), None | Some("no")) =>
+            {
+                return HazardKind::NoSidewalk;
+            }
+            _ => {}
+        }
+
+        match tag("kerb") {
+            Some("raised") => HazardKind::RaisedKerb,
+            Some("no" | "unknown") => HazardKind::MissingKerbRamp,
+            _ => HazardKind::Generic,
+        }
+    }
+}
+
+// Which hazard kinds are reported, so alerts can be tuned to the wearer and
+// the walk. `All` keeps the previous behaviour.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardProfile {
+    #[default]
+    All,
+    // Signals and crossings only, for familiar routes
+    Crossings,
+    // Kerbs, steps and surfaces, for wheelchair and cane users
+    Mobility,
+}
+
+impl HazardProfile {
+    pub fn includes(&self, kind: HazardKind) -> bool {
+        match self {
+            HazardProfile::All => true,
+            HazardProfile::Crossings => matches!(
+                kind,
+                HazardKind::SilentSignals
+                    | HazardKind::UnmarkedCrossing
+                    | HazardKind::UncontrolledCrossing
+                    | HazardKind::CrossingWithoutTactilePaving
+                    | HazardKind::Crossing
+            ),
+            HazardProfile::Mobility => matches!(
+                kind,
+                HazardKind::RaisedKerb
+                    | HazardKind::MissingKerbRamp
+                    | HazardKind::UnevenSurface
+                    | HazardKind::NoSidewalk
+                    | HazardKind::StepsWithoutHandrail
+                    | HazardKind::Steps
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct HazardReport {
     pub hazard: Element,
+    pub kind: HazardKind,
     pub distance: f64,
+    pub distance_m: f64,
     pub severity: HazardSeverity,
     pub vector: Vector,
 }
 
 impl HazardAnalyzer {
     pub fn new(lat: f64, lon: f64, elements: Vec<Element>) -> Self {
-        Self { lat, lon, elements }
+        Self {
+            lat,
+            lon,
+            elements,
+            profile: HazardProfile::default(),
+        }
+    }
+
+    pub fn set_profile(&mut self, profile: HazardProfile) {
+        self.profile = profile;
     }
 
     pub fn update_location(&mut self, point: Point) {
@@ -43,14 +159,13 @@ impl HazardAnalyzer {
         if hazards.is_empty() {
             None
         } else {
-            let reports = hazards
+            let mut reports: Vec<HazardReport> = hazards
                 .into_iter()
                 .map(|hazard| {
                     let locations = hazard.location().unwrap();
 
                     let mut min_distance = f64::MAX;
-                    let mut x_diff = 0.0;
-                    let mut y_diff = 0.0;
+                    let mut nearest = locations[0];
 
                     for point in locations {
                         let distance = ((point.lat - self.lat).powi(2)
@@ -58,11 +173,13 @@ impl HazardAnalyzer {
                         .sqrt();
                         if distance < min_distance {
                             min_distance = distance;
-                            x_diff = point.lon - self.lon;
-                            y_diff = point.lat - self.lat;
+                            nearest = point;
                         }
                     }
 
+                    let x_diff = nearest.lon - self.lon;
+                    let y_diff = nearest.lat - self.lat;
+
                     let severity = if min_distance < 0.0003 {
                         HazardSeverity::High
                     } else if min_distance < 0.0006 {
@@ -73,15 +190,34 @@ impl HazardAnalyzer {
 
                     let vector = Vector::new(f64::atan2(y_diff, x_diff), min_distance);
 
+                    let user = Point {
+                        lat: self.lat,
+                        lon: self.lon,
+                    };
+
                     HazardReport {
                         hazard: hazard.clone(),
+                        kind: HazardKind::classify(hazard),
                         distance: min_distance,
+                        distance_m: user.distance_m(&nearest),
                         severity,
                         vector,
                     }
                 })
                 .collect();
 
+            // Nearest first
+            reports.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+            for report in &reports {
+                debug!(
+                    "{:?} {} at {:.1} m, {:?} severity",
+                    report.kind,
+                    report.hazard.id(),
+                    report.distance_m,
+                    report.severity
+                );
+            }
+
             Some(reports)
         }
     }
@@ -89,6 +225,7 @@ impl HazardAnalyzer {
     pub fn nearby_hazards(&self, radius: f64) -> Vec<&Element> {
         self.elements
             .iter()
+            .filter(|element| self.profile.includes(HazardKind::classify(element)))
             .filter(|element| {
                 if let Some(locations) = element.location() {
                     for point in locations {
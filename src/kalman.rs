@@ -0,0 +1,257 @@
+use crate::gps::GNRMC;
+use crate::overpass::Point;
+use serde::{Deserialize, Serialize};
+
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+// Velocity is unknown until a few fixes have come in
+const INITIAL_VELOCITY_VARIANCE: f64 = 4.0;
+
+// Below this the direction of travel is mostly noise
+const MIN_HEADING_SPEED_MPS: f64 = 0.2;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KalmanConfig {
+    // Standard deviation of the walker's acceleration, m/s². Higher follows
+    // turns and stops faster, lower smooths more.
+    pub acceleration_noise: f64,
+    // Position error per unit of HDOP, m
+    pub uere_m: f64,
+    // Position error assumed when the receiver reports no HDOP, m
+    pub default_accuracy_m: f64,
+}
+
+impl Default for KalmanConfig {
+    fn default() -> Self {
+        Self {
+            acceleration_noise: 0.5,
+            uere_m: 4.0,
+            default_accuracy_m: 5.0,
+        }
+    }
+}
+
+// Position and velocity along one axis, in meters, with their covariance.
+// East and north are independent under a constant-velocity model, so two of
+// these are the same as one four-state filter.
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    position: f64,
+    velocity: f64,
+    covariance: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn new(position: f64, variance: f64) -> Self {
+        Self {
+            position,
+            velocity: 0.0,
+            covariance: [[variance, 0.0], [0.0, INITIAL_VELOCITY_VARIANCE]],
+        }
+    }
+
+    // x = F x, P = F P F' + Q with F = [1 dt; 0 1] and white-noise
+    // acceleration of variance `q`
+    fn predict(&mut self, dt: f64, q: f64) {
+        let [[pp, pv], [vp, vv]] = self.covariance;
+
+        self.position += self.velocity * dt;
+        self.covariance = [
+            [
+                pp + dt * (pv + vp) + dt * dt * vv + q * dt.powi(4) / 4.0,
+                pv + dt * vv + q * dt.powi(3) / 2.0,
+            ],
+            [
+                vp + dt * vv + q * dt.powi(3) / 2.0,
+                vv + q * dt * dt,
+            ],
+        ];
+    }
+
+    // Measurement of the position with variance `r`
+    fn update(&mut self, measured: f64, r: f64) {
+        let [[pp, pv], [vp, vv]] = self.covariance;
+        let innovation = measured - self.position;
+        let gain = [pp / (pp + r), vp / (pp + r)];
+
+        self.position += gain[0] * innovation;
+        self.velocity += gain[1] * innovation;
+        self.covariance = [
+            [(1.0 - gain[0]) * pp, (1.0 - gain[0]) * pv],
+            [vp - gain[1] * pp, vv - gain[1] * pv],
+        ];
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Estimate {
+    pub position: Point,
+    pub speed_mps: f64,
+    // Radians counter-clockwise from east in lat/lon space, the same as
+    // hazard vectors. None while standing still.
+    pub heading: Option<f64>,
+    // Root mean square of the position error
+    pub position_accuracy_m: f64,
+    // One standard deviation, radians
+    pub heading_accuracy: Option<f64>,
+}
+
+// Constant-velocity filter over the fixes in a local east/north frame around
+// the first valid fix
+pub struct KalmanFilter {
+    config: KalmanConfig,
+    origin: Option<Point>,
+    east: Axis,
+    north: Axis,
+}
+
+impl KalmanFilter {
+    pub fn new(config: KalmanConfig) -> Self {
+        Self {
+            config,
+            origin: None,
+            east: Axis::new(0.0, 0.0),
+            north: Axis::new(0.0, 0.0),
+        }
+    }
+
+    // Advances the filter by `dt` seconds and folds in the fix if it is
+    // valid. Returns None until the first valid fix.
+    pub fn update(&mut self, fix: &GNRMC, dt: f64) -> Option<Estimate> {
+        let q = self.config.acceleration_noise.powi(2);
+        let accuracy = fix
+            .hdop
+            .map_or(self.config.default_accuracy_m, |hdop| hdop * self.config.uere_m);
+
+        match self.origin {
+            Some(origin) => {
+                self.east.predict(dt, q);
+                self.north.predict(dt, q);
+
+                if fix.status == 1 {
+                    let (east, north) = to_local(origin, fix.google_coordinates());
+                    self.east.update(east, accuracy.powi(2));
+                    self.north.update(north, accuracy.powi(2));
+                }
+            }
+            None if fix.status == 1 => {
+                self.origin = Some(fix.google_coordinates());
+                self.east = Axis::new(0.0, accuracy.powi(2));
+                self.north = Axis::new(0.0, accuracy.powi(2));
+            }
+            None => {}
+        }
+
+        self.estimate()
+    }
+
+    // Advances the filter by `dt` seconds without a fix, moving the position
+    // by a displacement in meters (east, north) from step detection.
+    // `variance` is the displacement's error and adds to the position's.
+    pub fn dead_reckon(
+        &mut self,
+        dt: f64,
+        displacement: Option<(f64, f64)>,
+        variance: f64,
+    ) -> Option<Estimate> {
+        self.origin?;
+        let q = self.config.acceleration_noise.powi(2);
+
+        if let Some((east, north)) = displacement {
+            self.east.position += east;
+            self.north.position += north;
+            self.east.covariance[0][0] += variance / 2.0;
+            self.north.covariance[0][0] += variance / 2.0;
+        }
+        self.east.covariance[1][1] += q * dt * dt;
+        self.north.covariance[1][1] += q * dt * dt;
+
+        self.estimate()
+    }
+
+    pub fn estimate(&self) -> Option<Estimate> {
+        let origin = self.origin?;
+
+        let speed_mps = self.east.velocity.hypot(self.north.velocity);
+        let velocity_accuracy =
+            ((self.east.covariance[1][1] + self.north.covariance[1][1]) / 2.0).sqrt();
+        let moving = speed_mps > MIN_HEADING_SPEED_MPS.max(2.0 * velocity_accuracy);
+
+        Some(Estimate {
+            position: from_local(origin, self.east.position, self.north.position),
+            speed_mps,
+            heading: moving.then(|| {
+                (self.north.velocity * origin.lat.to_radians().cos()).atan2(self.east.velocity)
+            }),
+            position_accuracy_m: (self.east.covariance[0][0] + self.north.covariance[0][0]).sqrt(),
+            heading_accuracy: moving.then(|| (velocity_accuracy / speed_mps).atan()),
+        })
+    }
+}
+
+fn to_local(origin: Point, point: Point) -> (f64, f64) {
+    (
+        (point.lon - origin.lon) * METERS_PER_DEGREE * origin.lat.to_radians().cos(),
+        (point.lat - origin.lat) * METERS_PER_DEGREE,
+    )
+}
+
+fn from_local(origin: Point, east: f64, north: f64) -> Point {
+    Point {
+        lat: origin.lat + north / METERS_PER_DEGREE,
+        lon: origin.lon + east / (METERS_PER_DEGREE * origin.lat.to_radians().cos()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kalman::{KalmanConfig, KalmanFilter};
+    use crate::overpass::Point;
+    use crate::position::{GpsSimulator, PositionSource, SimulatorOptions};
+
+    #[tokio::test(start_paused = true)]
+    async fn smooths_noisy_walk() {
+        let start = Point {
+            lat: 33.4235,
+            lon: -111.9328,
+        };
+        let end = Point {
+            lat: 33.4235,
+            lon: -111.9310,
+        };
+
+        // Walking east at 1.4 m/s with 3 m of noise per fix
+        let mut simulator = GpsSimulator::new(SimulatorOptions {
+            waypoints: vec![start, end],
+            noise_m: 3.0,
+            multipath_probability: 0.0,
+            dropout_probability: 0.0,
+            seed: Some(7),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut filter = KalmanFilter::new(KalmanConfig::default());
+
+        let mut estimate = None;
+        for _ in 0..300 {
+            let fix = simulator.next_fix().await.unwrap();
+            estimate = filter.update(&fix, 0.1);
+        }
+        let estimate = estimate.unwrap();
+
+        // 30 s in; raw fixes are scattered by meters
+        let truth = Point {
+            lat: start.lat,
+            lon: start.lon + 42.0 / (111_320.0 * start.lat.to_radians().cos()),
+        };
+        assert!(estimate.position.distance_m(&truth) < 2.0, "{:?}", estimate);
+        assert!((estimate.speed_mps - 1.4).abs() < 0.3, "{:?}", estimate);
+        assert!(estimate.heading.unwrap().abs() < 15f64.to_radians(), "{:?}", estimate);
+        assert!(estimate.position_accuracy_m < 3.0);
+
+        // A lost fix only widens the uncertainty
+        let lost = filter.update(&Default::default(), 1.0).unwrap();
+        assert!(lost.position_accuracy_m > estimate.position_accuracy_m);
+    }
+}
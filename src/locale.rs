@@ -0,0 +1,113 @@
+use crate::hazard_analyzer::HazardKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    De,
+}
+
+impl Locale {
+    fn catalog_source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.json"),
+            Locale::Es => include_str!("../locales/es.json"),
+            Locale::De => include_str!("../locales/de.json"),
+        }
+    }
+
+    pub fn espeak_voice(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::De => "de",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+const FEET_PER_METER: f64 = 3.28084;
+
+// Spoken messages for one locale, keyed by message id. Ids missing from the
+// locale's catalog fall back to English, then to the id itself.
+pub struct Catalog {
+    units: Units,
+    messages: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Catalog {
+    pub fn new(locale: Locale, units: Units) -> Self {
+        let parse = |locale: Locale| {
+            serde_json::from_str::<HashMap<String, String>>(locale.catalog_source())
+                .expect("Invalid message catalog")
+        };
+
+        Self {
+            units,
+            messages: parse(locale),
+            fallback: parse(Locale::En),
+        }
+    }
+
+    // Looks up `id` and replaces each `{name}` placeholder with its parameter
+    pub fn get(&self, id: &str, params: &[(&str, &str)]) -> String {
+        let template = self
+            .messages
+            .get(id)
+            .or_else(|| self.fallback.get(id))
+            .map(|t| t.as_str())
+            .unwrap_or(id);
+
+        params
+            .iter()
+            .fold(template.to_string(), |message, (name, value)| {
+                message.replace(&format!("{{{}}}", name), value)
+            })
+    }
+
+    pub fn hazard_kind(&self, kind: HazardKind) -> String {
+        let id = match kind {
+            HazardKind::SilentSignals => "kind.silent_signals",
+            HazardKind::UnmarkedCrossing => "kind.unmarked_crossing",
+            HazardKind::UncontrolledCrossing => "kind.uncontrolled_crossing",
+            HazardKind::CrossingWithoutTactilePaving => "kind.crossing_without_tactile_paving",
+            HazardKind::Crossing => "kind.crossing",
+            HazardKind::RaisedKerb => "kind.raised_kerb",
+            HazardKind::MissingKerbRamp => "kind.missing_kerb_ramp",
+            HazardKind::UnevenSurface => "kind.uneven_surface",
+            HazardKind::NoSidewalk => "kind.no_sidewalk",
+            HazardKind::StepsWithoutHandrail => "kind.steps_without_handrail",
+            HazardKind::Steps => "kind.steps",
+            HazardKind::Generic => "kind.generic",
+        };
+
+        self.get(id, &[])
+    }
+
+    pub fn distance(&self, meters: f64) -> String {
+        let (value, one, many) = match self.units {
+            Units::Metric => (meters, "distance.meter", "distance.meters"),
+            Units::Imperial => (meters * FEET_PER_METER, "distance.foot", "distance.feet"),
+        };
+
+        let count = value.round().max(1.0) as u64;
+
+        if count == 1 {
+            self.get(one, &[])
+        } else {
+            self.get(many, &[("count", &count.to_string())])
+        }
+    }
+}
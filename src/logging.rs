@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry, fmt, reload};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // Same syntax as RUST_LOG without regexes, e.g. "info,safewalk::gps=debug".
+    // RUST_LOG takes precedence when set.
+    pub filter: String,
+    // Records kept in memory for /logs
+    pub buffer_size: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            buffer_size: 2000,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LogRecord {
+    // Increases by one per record, for resuming with ?since=
+    pub seq: u64,
+    // Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+lazy_static! {
+    static ref LOG_BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer {
+        records: VecDeque::new(),
+        capacity: LogConfig::default().buffer_size,
+        next_seq: 0,
+    });
+
+    static ref LOG_UPDATES: broadcast::Sender<LogRecord> = broadcast::channel(256).0;
+}
+
+static FILTER: OnceLock<(reload::Handle<Targets, Registry>, Mutex<String>)> = OnceLock::new();
+
+// Collects the message and any extra fields of an event. Events forwarded
+// from the `log` crate carry their real target in the `log.target` field.
+#[derive(Default)]
+struct RecordVisitor {
+    message: String,
+    fields: String,
+    log_target: Option<String>,
+}
+
+impl Visit for RecordVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            "log.target" => self.log_target = Some(value.to_string()),
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {}={}", name, value);
+            }
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name if name.starts_with("log.") => {}
+            name => {
+                let _ = write!(self.fields, " {}={:?}", name, value);
+            }
+        }
+    }
+}
+
+struct BufferLayer;
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let mut visitor = RecordVisitor::default();
+        event.record(&mut visitor);
+
+        let metadata = event.metadata();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+
+        let mut buffer = LOG_BUFFER.lock().unwrap();
+        let record = LogRecord {
+            seq: buffer.next_seq,
+            timestamp,
+            level: metadata.level().to_string(),
+            target: visitor
+                .log_target
+                .unwrap_or_else(|| metadata.target().to_string()),
+            message: visitor.message + &visitor.fields,
+        };
+
+        buffer.next_seq += 1;
+        if buffer.records.len() >= buffer.capacity {
+            buffer.records.pop_front();
+        }
+        buffer.records.push_back(record.clone());
+        drop(buffer);
+
+        let _ = LOG_UPDATES.send(record);
+    }
+}
+
+// Replaces `tracing_subscriber::fmt::init`: console output as before, plus
+// the in-memory buffer, with a filter that can be changed at runtime
+pub fn init(config: &LogConfig) -> Result<()> {
+    let directives = std::env::var("RUST_LOG").unwrap_or_else(|_| config.filter.clone());
+    let targets = Targets::from_str(&directives)
+        .with_context(|| format!("Invalid log filter {:?}", directives))?;
+
+    LOG_BUFFER.lock().unwrap().capacity = config.buffer_size.max(1);
+
+    let (filter, handle) = reload::Layer::new(targets);
+    let _ = FILTER.set((handle, Mutex::new(directives)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(BufferLayer)
+        .try_init()?;
+
+    // `log` records must not be capped at the startup filter; the reloadable
+    // one decides, so `Logs::set_filter` can raise levels later
+    log::set_max_level(log::LevelFilter::Trace);
+
+    Ok(())
+}
+
+pub struct Logs;
+
+impl Logs {
+    // Records with seq >= `since` at `level` or more severe, whose target
+    // starts with `target`, newest `limit` of them
+    pub fn query(since: u64, level: Level, target: Option<&str>, limit: usize) -> Vec<LogRecord> {
+        let buffer = LOG_BUFFER.lock().unwrap();
+
+        let matching = buffer
+            .records
+            .iter()
+            .filter(|record| record.seq >= since && Self::matches(record, level, target))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        matching[matching.len().saturating_sub(limit)..].to_vec()
+    }
+
+    pub fn matches(record: &LogRecord, level: Level, target: Option<&str>) -> bool {
+        // tracing orders levels by verbosity: ERROR < WARN < ... < TRACE
+        Level::from_str(&record.level).is_ok_and(|l| l <= level)
+            && target.is_none_or(|t| record.target.starts_with(t))
+    }
+
+    pub fn subscribe() -> broadcast::Receiver<LogRecord> {
+        LOG_UPDATES.subscribe()
+    }
+
+    pub fn filter() -> Option<String> {
+        FILTER.get().map(|(_, current)| current.lock().unwrap().clone())
+    }
+
+    pub fn set_filter(directives: &str) -> Result<()> {
+        let targets = Targets::from_str(directives)
+            .with_context(|| format!("Invalid log filter {:?}", directives))?;
+        let (handle, current) = FILTER.get().context("Logging not initialized")?;
+
+        handle.reload(targets)?;
+        *current.lock().unwrap() = directives.to_string();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::{LogConfig, LogRecord, Logs, init};
+    use tracing::Level;
+
+    #[test]
+    fn level_and_target_matching() {
+        let record = LogRecord {
+            seq: 0,
+            timestamp: 0,
+            level: "WARN".to_string(),
+            target: "safewalk::gps".to_string(),
+            message: "Bad checksum".to_string(),
+        };
+
+        assert!(Logs::matches(&record, Level::INFO, None));
+        assert!(Logs::matches(&record, Level::WARN, Some("safewalk::gps")));
+        assert!(!Logs::matches(&record, Level::ERROR, None));
+        assert!(!Logs::matches(&record, Level::TRACE, Some("safewalk::speech")));
+    }
+
+    #[test]
+    fn raised_level_takes_effect() {
+        init(&LogConfig::default()).unwrap();
+        let debug = |since| Logs::query(since, Level::DEBUG, Some("safewalk::logging"), 10);
+
+        log::debug!("Dropped at info");
+        assert!(debug(0).is_empty());
+
+        Logs::set_filter("info,safewalk::logging=debug").unwrap();
+        log::debug!("Kept at debug");
+        let records = debug(0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].level, "DEBUG");
+        assert_eq!(records[0].message, "Kept at debug");
+    }
+}
@@ -1,18 +1,34 @@
+mod announcement;
 mod button;
-mod espeak;
+mod clock;
+mod compass;
+mod config;
+mod dead_reckoning;
+mod earcon;
+mod fix_quality;
 mod gps;
 mod hazard_analyzer;
+mod kalman;
+mod locale;
+mod logging;
 mod motor;
 mod networking;
 mod overpass;
+mod position;
 mod safewalk;
+#[cfg(test)]
+mod scenario;
+mod session;
+mod speech;
+mod util;
 
 use crate::button::Button;
+use crate::config::Config;
 use crate::motor::Motor;
-use crate::networking::Telemetry;
+use crate::networking::{Metrics, Telemetry};
 use crate::overpass::{OverpassResponse, Point, fetch};
 use crate::safewalk::SafeWalk;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use networking::start_ap;
 use std::env;
 use std::path::PathBuf;
@@ -25,8 +41,8 @@ use tokio::time::sleep;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    // let args = env::args().collect::<Vec<String>>();
+    Metrics::start();
+
     // let data = if args.contains(&"--cache".to_string()) {
     //     println!("Using cached data");
     //
@@ -44,9 +60,21 @@ async fn main() -> Result<()> {
 
     // start_ap().await;
 
-    Telemetry::init(3000);
+    let config = Config::load(&PathBuf::from("config.json"))?;
+    logging::init(&config.log)?;
+
+    // safewalk --replay <session.jsonl> [speed]
+    let args = env::args().collect::<Vec<String>>();
+    if let Some(i) = args.iter().position(|arg| arg == "--replay") {
+        let path = args.get(i + 1).context("--replay needs a session file")?;
+        let speed = args.get(i + 2).and_then(|speed| speed.parse().ok()).unwrap_or(1.0);
+
+        return session::run_replay(&PathBuf::from(path), speed, &config).await;
+    }
+
+    Telemetry::init(config.server.clone()).await?;
 
-    let mut safewalk = SafeWalk::new().await;
+    let mut safewalk = SafeWalk::new(&config).await?;
 
     let shutdown = Arc::new(Notify::new());
     let shutdown_clone = shutdown.clone();
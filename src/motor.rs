@@ -1,4 +1,6 @@
+use crate::util::BoxFuture;
 use rppal::gpio::{Gpio, OutputPin};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::sync::Arc;
 use std::time::Duration;
@@ -6,6 +8,23 @@ use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Front,
+    Back,
+    Left,
+    Right,
+}
+
+// What the vibration system drives: a GPIO motor on the device, a recording
+// mock in scenario tests
+pub trait MotorOutput: Send + Sync {
+    // Power from 0.0 to 1.0
+    fn set(&self, power: f64) -> BoxFuture<'_, ()>;
+    fn off(&self) -> BoxFuture<'_, ()>;
+}
+
 #[derive(Clone)]
 pub struct Motor {
     state: Arc<Mutex<MotorState>>,
@@ -86,3 +105,13 @@ impl Motor {
         state.mode = MotorMode::Pwm;
     }
 }
+
+impl MotorOutput for Motor {
+    fn set(&self, power: f64) -> BoxFuture<'_, ()> {
+        Box::pin(Motor::set(self, power))
+    }
+
+    fn off(&self) -> BoxFuture<'_, ()> {
+        Box::pin(Motor::off(self))
+    }
+}
@@ -0,0 +1,164 @@
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    ReadOnly,
+    Admin,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Credential {
+    pub token: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    // Accepted as "Authorization: Bearer <token>", as the password of HTTP
+    // Basic auth (any user name), or as ?token= for EventSource/WebSocket
+    pub credentials: Vec<Credential>,
+    // Role of requests without valid credentials; null rejects them.
+    // Defaults to read-only, so writes need an admin credential.
+    pub anonymous: Option<Role>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            credentials: Vec::new(),
+            anonymous: Some(Role::ReadOnly),
+        }
+    }
+}
+
+impl AuthConfig {
+    fn role_for(&self, token: Option<&str>) -> Option<Role> {
+        token
+            .and_then(|token| {
+                self.credentials
+                    .iter()
+                    .find(|credential| constant_time_eq(&credential.token, token))
+                    .map(|credential| credential.role)
+            })
+            .or(self.anonymous)
+    }
+}
+
+// Compares digests, so the time taken tells nothing about either length
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn token_from_headers(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        return Some(token.trim().to_string());
+    }
+
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(base64_decode(encoded.trim())?).ok()?;
+    let (_, password) = decoded.split_once(':')?;
+
+    Some(password.to_string())
+}
+
+fn token_from_query(query: Option<&str>) -> Option<String> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .and_then(|token| percent_decode_str(token).decode_utf8().ok())
+        .map(|token| token.to_string())
+}
+
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in input.bytes().filter(|c| *c != b'=') {
+        let value = ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 6) | value;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+// Middleware rejecting requests whose credentials don't grant `required`,
+// used with `middleware::from_fn_with_state`
+pub async fn require_role(
+    State((auth, required)): State<(Arc<AuthConfig>, Role)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token =
+        token_from_headers(request.headers()).or_else(|| token_from_query(request.uri().query()));
+
+    match auth.role_for(token.as_deref()) {
+        Some(role) if role >= required => next.run(request).await,
+        Some(_) => StatusCode::FORBIDDEN.into_response(),
+        None => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"safewalk\"")],
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::auth::{
+        AuthConfig, Credential, Role, token_from_headers, token_from_query,
+    };
+    use axum::http::{HeaderMap, HeaderValue, header};
+
+    #[test]
+    fn roles_from_credentials() {
+        let auth = AuthConfig {
+            credentials: vec![Credential {
+                token: "secret".to_string(),
+                role: Role::Admin,
+            }],
+            anonymous: Some(Role::ReadOnly),
+        };
+
+        let mut headers = HeaderMap::new();
+        // "admin:secret"
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic YWRtaW46c2VjcmV0"));
+        let token = token_from_headers(&headers);
+        assert_eq!(token.as_deref(), Some("secret"));
+
+        assert_eq!(auth.role_for(token.as_deref()), Some(Role::Admin));
+        assert_eq!(auth.role_for(Some("wrong")), Some(Role::ReadOnly));
+        assert_eq!(auth.role_for(Some("secret2")), Some(Role::ReadOnly));
+
+        // As encodeURIComponent sends it
+        let token = token_from_query(Some("since=0&token=s%2Bc%2Fr%3D%20t"));
+        assert_eq!(token.as_deref(), Some("s+c/r= t"));
+
+        let closed = AuthConfig {
+            anonymous: None,
+            ..auth
+        };
+        assert_eq!(closed.role_for(None), None);
+    }
+}
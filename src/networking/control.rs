@@ -0,0 +1,140 @@
+use crate::hazard_analyzer::HazardProfile;
+use crate::overpass::Point;
+use axum::extract::Json;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Router;
+use axum::routing::{post, put};
+use lazy_static::lazy_static;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+// Requests from the dashboard to the running `SafeWalk` loop, which applies
+// them at the start of its next iteration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ControlCommand {
+    Speak { text: String, urgent: bool },
+    PauseHaptics,
+    ResumeHaptics,
+    MotorTest,
+    SetHazardProfile(HazardProfile),
+    RefreshOverpass,
+    CalibrateCompass,
+    SetDestination(Option<Point>),
+}
+
+lazy_static! {
+    static ref COMMANDS: (
+        mpsc::Sender<ControlCommand>,
+        Mutex<Option<mpsc::Receiver<ControlCommand>>>
+    ) = {
+        let (tx, rx) = mpsc::channel(32);
+        (tx, Mutex::new(Some(rx)))
+    };
+}
+
+pub struct Control;
+
+impl Control {
+    // The receiving end for the main loop; there is only one
+    pub fn take_receiver() -> Option<mpsc::Receiver<ControlCommand>> {
+        COMMANDS.1.lock().unwrap().take()
+    }
+
+    // Routes under /control; the caller is expected to restrict them to admins
+    pub fn routes() -> Router {
+        Router::new()
+            .route("/control/speak", post(speak))
+            .route("/control/haptics/pause", post(pause_haptics))
+            .route("/control/haptics/resume", post(resume_haptics))
+            .route("/control/motor-test", post(motor_test))
+            .route("/control/hazard-profile", put(set_hazard_profile))
+            .route("/control/overpass/refresh", post(refresh_overpass))
+            .route("/control/compass/calibrate", post(calibrate_compass))
+            .route(
+                "/control/destination",
+                put(set_destination).delete(clear_destination),
+            )
+    }
+}
+
+fn send(command: ControlCommand) -> impl IntoResponse {
+    match COMMANDS.0.try_send(command) {
+        Ok(()) => (StatusCode::ACCEPTED, Json(json!({"status": "queued"}))),
+        Err(TrySendError::Full(command)) => {
+            warn!("Control queue full, dropping {:?}", command);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({"error": "too many pending commands"})),
+            )
+        }
+        Err(TrySendError::Closed(_)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "SafeWalk is not running"})),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct SpeakRequest {
+    text: String,
+    // Urgent messages interrupt status messages that are being spoken
+    #[serde(default)]
+    urgent: bool,
+}
+
+async fn speak(Json(request): Json<SpeakRequest>) -> impl IntoResponse {
+    send(ControlCommand::Speak {
+        text: request.text,
+        urgent: request.urgent,
+    })
+}
+
+async fn pause_haptics() -> impl IntoResponse {
+    send(ControlCommand::PauseHaptics)
+}
+
+async fn resume_haptics() -> impl IntoResponse {
+    send(ControlCommand::ResumeHaptics)
+}
+
+async fn motor_test() -> impl IntoResponse {
+    send(ControlCommand::MotorTest)
+}
+
+#[derive(Deserialize)]
+struct ProfileRequest {
+    profile: HazardProfile,
+}
+
+async fn set_hazard_profile(Json(request): Json<ProfileRequest>) -> impl IntoResponse {
+    send(ControlCommand::SetHazardProfile(request.profile))
+}
+
+async fn refresh_overpass() -> impl IntoResponse {
+    send(ControlCommand::RefreshOverpass)
+}
+
+async fn calibrate_compass() -> impl IntoResponse {
+    send(ControlCommand::CalibrateCompass)
+}
+
+async fn set_destination(Json(destination): Json<Point>) -> impl IntoResponse {
+    if !(-90.0..=90.0).contains(&destination.lat) || !(-180.0..=180.0).contains(&destination.lon) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "coordinates out of range"})),
+        )
+            .into_response();
+    }
+
+    send(ControlCommand::SetDestination(Some(destination))).into_response()
+}
+
+async fn clear_destination() -> impl IntoResponse {
+    send(ControlCommand::SetDestination(None))
+}
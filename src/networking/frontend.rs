@@ -0,0 +1,166 @@
+use axum::Router;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, Response, StatusCode, Uri, header};
+use axum::routing::get;
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tower_http::compression::CompressionLayer;
+
+// Static export of the Next.js dashboard (`npm run build` in frontend/).
+// With the `embed-frontend` feature it's compiled into the binary, otherwise
+// it's read from `ServerConfig::frontend_dir` on every request.
+#[cfg(feature = "embed-frontend")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "frontend/out/"]
+#[allow_missing = true]
+struct Assets;
+
+struct Asset {
+    data: Cow<'static, [u8]>,
+    etag: String,
+}
+
+#[cfg(feature = "embed-frontend")]
+async fn load(_dir: &Path, path: &str) -> Option<Asset> {
+    let file = Assets::get(path)?;
+    let hash = file.metadata.sha256_hash();
+
+    Some(Asset {
+        etag: format!(
+            "\"{}\"",
+            hash[..8].iter().map(|b| format!("{:02x}", b)).collect::<String>()
+        ),
+        data: file.data,
+    })
+}
+
+#[cfg(not(feature = "embed-frontend"))]
+async fn load(dir: &Path, path: &str) -> Option<Asset> {
+    let file = dir.join(path);
+    let metadata = tokio::fs::metadata(&file).await.ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+
+    Some(Asset {
+        etag: format!("W/\"{:x}-{:x}\"", metadata.len(), modified),
+        data: Cow::Owned(tokio::fs::read(&file).await.ok()?),
+    })
+}
+
+// Only plain relative paths, so nothing outside the export can be reached
+fn is_safe(path: &str) -> bool {
+    !path.contains('\\')
+        && Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+// Files to try for a request path. Next.js exports routes as `route.html`;
+// unknown routes without an extension fall back to the app shell so client
+// side routing still works.
+fn candidates(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        return vec!["index.html".to_string()];
+    }
+
+    if Path::new(path).extension().is_some() {
+        return vec![path.to_string()];
+    }
+
+    vec![
+        path.to_string(),
+        format!("{}.html", path),
+        format!("{}/index.html", path),
+        "index.html".to_string(),
+    ]
+}
+
+fn cache_control(path: &str) -> &'static str {
+    // Next.js puts a content hash in every file name under _next/static
+    if path.starts_with("_next/static/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    }
+}
+
+async fn serve(State(dir): State<Arc<PathBuf>>, headers: HeaderMap, uri: Uri) -> Response<Body> {
+    let path = uri.path().trim_start_matches('/').trim_end_matches('/');
+
+    if !path.is_empty() && !is_safe(path) {
+        return status(StatusCode::BAD_REQUEST);
+    }
+
+    for candidate in candidates(path) {
+        let Some(asset) = load(&dir, &candidate).await else {
+            continue;
+        };
+
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == asset.etag));
+
+        let builder = Response::builder()
+            .header(header::ETAG, &asset.etag)
+            .header(header::CACHE_CONTROL, cache_control(&candidate));
+
+        if not_modified {
+            return builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        let mime_type = mime_guess::from_path(&candidate).first_or_octet_stream();
+
+        return builder
+            .status(StatusCode::OK)
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+            )
+            .body(Body::from(asset.data))
+            .unwrap();
+    }
+
+    status(StatusCode::NOT_FOUND)
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder().status(code).body(Body::empty()).unwrap()
+}
+
+pub fn routes(dir: PathBuf) -> Router {
+    Router::new()
+        .route("/", get(serve))
+        .route("/{*path}", get(serve))
+        .with_state(Arc::new(dir))
+        .layer(CompressionLayer::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::frontend::{candidates, is_safe};
+
+    #[test]
+    fn paths() {
+        assert!(is_safe("_next/static/chunks/app.js"));
+        assert!(!is_safe("../config.json"));
+        assert!(!is_safe("a/../../etc/passwd"));
+        assert!(!is_safe("..\\config.json"));
+
+        assert_eq!(candidates(""), vec!["index.html"]);
+        assert_eq!(candidates("favicon.ico"), vec!["favicon.ico"]);
+        assert_eq!(candidates("map").last().unwrap(), "index.html");
+    }
+}
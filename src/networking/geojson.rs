@@ -0,0 +1,356 @@
+use crate::hazard_analyzer::{HazardKind, HazardReport};
+use crate::overpass::{Element, Point};
+use axum::Router;
+use axum::extract::{Json, Query};
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+// About an hour of walking at one point every few meters
+const TRACK_CAPACITY: usize = 3600;
+// Positions closer than this to the previous one don't extend the track
+const TRACK_MIN_STEP_M: f64 = 1.0;
+
+pub(super) struct MapState {
+    pub(super) elements: Vec<Element>,
+    hazards: Vec<HazardReport>,
+    // Position and milliseconds since the Unix epoch, oldest first
+    track: VecDeque<(Point, u64)>,
+}
+
+lazy_static! {
+    static ref MAP_STATE: RwLock<MapState> = RwLock::new(MapState {
+        elements: Vec::new(),
+        hazards: Vec::new(),
+        track: VecDeque::new(),
+    });
+}
+
+pub(super) async fn map_state() -> RwLockReadGuard<'static, MapState> {
+    MAP_STATE.read().await
+}
+
+// What the device currently sees, exposed as GeoJSON for maps and GIS tools
+pub struct MapData;
+
+impl MapData {
+    pub async fn set_elements(elements: &[Element]) {
+        MAP_STATE.write().await.elements = elements.to_vec();
+    }
+
+    pub async fn set_hazards(hazards: &[HazardReport]) {
+        MAP_STATE.write().await.hazards = hazards.to_vec();
+    }
+
+    pub async fn push_position(point: Point) {
+        let mut state = MAP_STATE.write().await;
+
+        if state
+            .track
+            .back()
+            .is_some_and(|(last, _)| last.distance_m(&point) < TRACK_MIN_STEP_M)
+        {
+            return;
+        }
+
+        if state.track.len() == TRACK_CAPACITY {
+            state.track.pop_front();
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        state.track.push_back((point, now));
+    }
+
+    pub fn routes() -> Router {
+        Router::new()
+            .route("/map/elements.geojson", get(elements))
+            .route("/map/hazards.geojson", get(hazards))
+            .route("/map/track.geojson", get(track))
+    }
+}
+
+// minlon,minlat,maxlon,maxlat as in the GeoJSON spec
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+// Whether the segment from `a` to `b` touches the rectangle from `min` to
+// `max`, by clipping it against each edge in turn (Liang-Barsky)
+pub fn segment_intersects(a: (f64, f64), b: (f64, f64), min: (f64, f64), max: (f64, f64)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut enter, mut exit) = (0.0f64, 1.0f64);
+
+    for (p, q) in [
+        (-dx, a.0 - min.0),
+        (dx, max.0 - a.0),
+        (-dy, a.1 - min.1),
+        (dy, max.1 - a.1),
+    ] {
+        if p == 0.0 {
+            // Parallel to this edge and outside it
+            if q < 0.0 {
+                return false;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+    }
+
+    enter <= exit
+}
+
+impl BBox {
+    pub fn parse(s: &str) -> Option<Self> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<f64>>>()?;
+
+        match values[..] {
+            [min_lon, min_lat, max_lon, max_lat] if min_lon <= max_lon && min_lat <= max_lat => {
+                Some(Self {
+                    min_lon,
+                    min_lat,
+                    max_lon,
+                    max_lat,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        (self.min_lon..=self.max_lon).contains(&point.lon)
+            && (self.min_lat..=self.max_lat).contains(&point.lat)
+    }
+
+    // True if any part of the element lies inside, including ways that only
+    // cross the box between two vertices
+    pub fn intersects(&self, element: &Element) -> bool {
+        let min = (self.min_lon, self.min_lat);
+        let max = (self.max_lon, self.max_lat);
+
+        match element.location().as_deref() {
+            None | Some([]) => false,
+            Some([point]) => self.contains(point),
+            Some(points) => points
+                .windows(2)
+                .any(|w| segment_intersects((w[0].lon, w[0].lat), (w[1].lon, w[1].lat), min, max)),
+        }
+    }
+}
+
+fn position(point: &Point) -> Value {
+    json!([point.lon, point.lat])
+}
+
+fn geometry(element: &Element) -> Option<Value> {
+    match element {
+        Element::Node { lat, lon, .. } => Some(json!({
+            "type": "Point",
+            "coordinates": [lon, lat],
+        })),
+        Element::Way { geometry, .. } => Some(json!({
+            "type": "LineString",
+            "coordinates": geometry.iter().map(position).collect::<Vec<_>>(),
+        })),
+        // Members aren't resolved, so relations have no geometry
+        Element::Relation { .. } => None,
+    }
+}
+
+fn osm_type(element: &Element) -> &'static str {
+    match element {
+        Element::Node { .. } => "node",
+        Element::Way { .. } => "way",
+        Element::Relation { .. } => "relation",
+    }
+}
+
+pub fn element_feature(element: &Element) -> Option<Value> {
+    Some(json!({
+        "type": "Feature",
+        "id": format!("{}/{}", osm_type(element), element.id()),
+        "geometry": geometry(element)?,
+        "properties": {
+            "osm_type": osm_type(element),
+            "osm_id": element.id(),
+            "kind": HazardKind::classify(element),
+            "tags": element.tags(),
+        },
+    }))
+}
+
+pub fn hazard_feature(report: &HazardReport) -> Option<Value> {
+    let mut feature = element_feature(&report.hazard)?;
+
+    let properties = feature["properties"].as_object_mut()?;
+    properties.insert("kind".to_string(), json!(report.kind));
+    properties.insert("severity".to_string(), json!(report.severity));
+    properties.insert("distance_m".to_string(), json!(report.distance_m));
+
+    Some(feature)
+}
+
+fn collection(features: Vec<Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+#[derive(Deserialize)]
+struct MapQuery {
+    bbox: Option<String>,
+}
+
+impl MapQuery {
+    fn bbox(&self) -> Result<Option<BBox>, (StatusCode, Json<Value>)> {
+        match &self.bbox {
+            None => Ok(None),
+            Some(s) => BBox::parse(s).map(Some).ok_or((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "bbox must be minlon,minlat,maxlon,maxlat"})),
+            )),
+        }
+    }
+}
+
+fn geojson(value: Value) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/geo+json")], value.to_string())
+}
+
+async fn elements(Query(query): Query<MapQuery>) -> impl IntoResponse {
+    let bbox = match query.bbox() {
+        Ok(bbox) => bbox,
+        Err(e) => return e.into_response(),
+    };
+    let state = MAP_STATE.read().await;
+
+    let features = state
+        .elements
+        .iter()
+        .filter(|element| bbox.is_none_or(|b| b.intersects(element)))
+        .filter_map(element_feature)
+        .collect();
+
+    geojson(collection(features)).into_response()
+}
+
+async fn hazards(Query(query): Query<MapQuery>) -> impl IntoResponse {
+    let bbox = match query.bbox() {
+        Ok(bbox) => bbox,
+        Err(e) => return e.into_response(),
+    };
+    let state = MAP_STATE.read().await;
+
+    let features = state
+        .hazards
+        .iter()
+        .filter(|report| bbox.is_none_or(|b| b.intersects(&report.hazard)))
+        .filter_map(hazard_feature)
+        .collect();
+
+    geojson(collection(features)).into_response()
+}
+
+// One LineString with per-vertex times in `coordTimes`, the convention used
+// by GPX to GeoJSON converters
+async fn track(Query(query): Query<MapQuery>) -> impl IntoResponse {
+    let bbox = match query.bbox() {
+        Ok(bbox) => bbox,
+        Err(e) => return e.into_response(),
+    };
+    let state = MAP_STATE.read().await;
+
+    let points = state
+        .track
+        .iter()
+        .filter(|(point, _)| bbox.is_none_or(|b| b.contains(point)))
+        .collect::<Vec<_>>();
+
+    let features = if points.is_empty() {
+        Vec::new()
+    } else {
+        vec![json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": points.iter().map(|(point, _)| position(point)).collect::<Vec<_>>(),
+            },
+            "properties": {
+                "coordTimes": points.iter().map(|(_, t)| t).collect::<Vec<_>>(),
+            },
+        })]
+    };
+
+    geojson(collection(features)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::geojson::{BBox, element_feature};
+    use crate::overpass::{Element, OverpassBounds, Point};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn features_and_bbox() {
+        let node = Element::Node {
+            id: 42,
+            lat: 33.42,
+            lon: -111.93,
+            tags: HashMap::from([("highway".to_string(), "crossing".to_string())]),
+        };
+
+        let feature = element_feature(&node).unwrap();
+        assert_eq!(feature["id"], json!("node/42"));
+        assert_eq!(feature["geometry"]["coordinates"], json!([-111.93, 33.42]));
+        assert_eq!(feature["properties"]["kind"], json!("Crossing"));
+
+        let bbox = BBox::parse("-112,33,-111,34").unwrap();
+        assert!(bbox.intersects(&node));
+        assert!(!bbox.contains(&Point { lat: 35.0, lon: -111.5 }));
+
+        // Crosses the box with both vertices outside it, then passes by
+        let way = |geometry: Vec<Point>| Element::Way {
+            bounds: OverpassBounds {
+                max_lat: 0.0,
+                max_lon: 0.0,
+                min_lat: 0.0,
+                min_lon: 0.0,
+            },
+            geometry,
+            id: 7,
+            nodes: None,
+            tags: HashMap::new(),
+        };
+        let crossing = way(vec![
+            Point { lat: 33.5, lon: -113.0 },
+            Point { lat: 33.5, lon: -110.0 },
+        ]);
+        assert!(bbox.intersects(&crossing));
+        let passing = way(vec![
+            Point { lat: 32.0, lon: -113.0 },
+            Point { lat: 35.0, lon: -113.5 },
+        ]);
+        assert!(!bbox.intersects(&passing));
+
+        assert_eq!(BBox::parse("-111,33,-112,34"), None);
+        assert_eq!(BBox::parse("1,2,3"), None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::networking::schema::{TELEMETRY_SCHEMA, ValueType};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+// 10 minutes of 10 Hz updates per key, longer while values hold
+pub const HISTORY_CAPACITY: usize = 6000;
+
+// An unchanged value is recorded again after this long, so charts still
+// cover steady stretches
+pub const HISTORY_INTERVAL_MS: u64 = 1000;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Sample {
+    // Milliseconds since the Unix epoch
+    pub t: u64,
+    pub v: Value,
+}
+
+// Bounded history of one telemetry key, oldest first
+pub struct History {
+    samples: VecDeque<Sample>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    // Skips values equal to the last sample unless `HISTORY_INTERVAL_MS`
+    // has passed since it
+    pub fn record(&mut self, t: u64, v: Value) {
+        if self
+            .samples
+            .back()
+            .is_some_and(|last| last.v == v && t < last.t + HISTORY_INTERVAL_MS)
+        {
+            return;
+        }
+
+        self.push(t, v);
+    }
+
+    pub fn push(&mut self, t: u64, v: Value) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(Sample { t, v });
+    }
+
+    pub fn since(&self, since: u64) -> Vec<Sample> {
+        let start = self.samples.partition_point(|sample| sample.t < since);
+
+        self.samples.range(start..).cloned().collect()
+    }
+}
+
+// Whether a key's values are kept for charts: numbers and arrays of numbers
+// from the schema. Hazard reports hold whole map elements and aren't kept.
+pub fn charted(key: &str, value: &Value) -> bool {
+    let Some(schema) = TELEMETRY_SCHEMA.iter().find(|schema| schema.key == key) else {
+        return false;
+    };
+
+    match schema.value_type {
+        // Null marks a gap, e.g. no course while standing still
+        ValueType::Number => value.is_number() || value.is_null(),
+        ValueType::Array => value
+            .as_array()
+            .is_some_and(|values| values.iter().all(Value::is_number)),
+        ValueType::String => false,
+    }
+}
+
+// Reduces `samples` to at most `limit` points by merging runs of consecutive
+// samples. Numbers and arrays of numbers are averaged, anything else keeps the
+// latest value; each merged point carries the timestamp of its last sample.
+pub fn downsample(samples: Vec<Sample>, limit: usize) -> Vec<Sample> {
+    if limit == 0 || samples.len() <= limit {
+        return samples;
+    }
+
+    let chunk_size = samples.len().div_ceil(limit);
+
+    samples
+        .chunks(chunk_size)
+        .map(|chunk| Sample {
+            t: chunk.last().unwrap().t,
+            v: average(chunk).unwrap_or_else(|| chunk.last().unwrap().v.clone()),
+        })
+        .collect()
+}
+
+fn average(chunk: &[Sample]) -> Option<Value> {
+    let n = chunk.len() as f64;
+
+    match &chunk.last()?.v {
+        Value::Number(_) => {
+            let sum = chunk
+                .iter()
+                .map(|sample| sample.v.as_f64())
+                .sum::<Option<f64>>()?;
+
+            Some(Value::from(sum / n))
+        }
+        Value::Array(last) => {
+            let mut sums = vec![0.0; last.len()];
+
+            for sample in chunk {
+                let values = sample.v.as_array().filter(|a| a.len() == sums.len())?;
+                for (sum, value) in sums.iter_mut().zip(values) {
+                    *sum += value.as_f64()?;
+                }
+            }
+
+            Some(Value::from(
+                sums.into_iter().map(|sum| sum / n).collect::<Vec<f64>>(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::history::{History, charted, downsample};
+    use serde_json::json;
+
+    #[test]
+    fn ring_buffer_and_downsample() {
+        let mut history = History::new(4);
+        for t in 0..6 {
+            history.push(t, json!(t as f64));
+        }
+
+        // Oldest two were evicted
+        let all = history.since(0);
+        assert_eq!(all.iter().map(|s| s.t).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+        assert_eq!(history.since(4).len(), 2);
+
+        let reduced = downsample(all, 2);
+        assert_eq!(reduced.len(), 2);
+        assert_eq!(reduced[0].t, 3);
+        assert_eq!(reduced[0].v, json!(2.5));
+        assert_eq!(reduced[1].v, json!(4.5));
+
+        let mut speeds = History::new(10);
+        speeds.push(0, json!([1.0, 0.0]));
+        speeds.push(1, json!([0.0, 0.5]));
+        assert_eq!(downsample(speeds.since(0), 1)[0].v, json!([0.5, 0.25]));
+
+        // Repeats are only kept once a second
+        let mut heading = History::new(10);
+        for t in (0..2000).step_by(100) {
+            heading.record(t, json!(1.5));
+        }
+        heading.record(2050, json!(1.6));
+        let times = heading.since(0).iter().map(|s| s.t).collect::<Vec<_>>();
+        assert_eq!(times, vec![0, 1000, 2050]);
+
+        assert!(charted("heading", &json!(1.5)));
+        assert!(charted("speeds", &json!([0.0, 0.5, 0.0, 0.0])));
+        assert!(!charted("hazards", &json!([{"distance_m": 12.0}])));
+        assert!(!charted("fix_mode", &json!("good")));
+        assert!(!charted("custom", &json!(3)));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::logging::Logs;
+use axum::Router;
+use axum::extract::{Json, Query};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, put};
+use serde::Deserialize;
+use serde_json::json;
+use std::convert::Infallible;
+use std::str::FromStr;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::Level;
+
+#[derive(Deserialize)]
+struct LogQuery {
+    // Most verbose level to include, e.g. "warn" for warnings and errors
+    level: Option<String>,
+    // Module prefix, e.g. "safewalk::hazard_analyzer"
+    target: Option<String>,
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+impl LogQuery {
+    fn level(&self) -> Result<Level, (StatusCode, Json<serde_json::Value>)> {
+        match &self.level {
+            None => Ok(Level::TRACE),
+            Some(level) => Level::from_str(level).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"error": "level must be one of error, warn, info, debug, trace"})),
+                )
+            }),
+        }
+    }
+}
+
+pub fn read_routes() -> Router {
+    Router::new()
+        .route("/logs", get(logs))
+        .route("/logs/stream", get(log_stream))
+        .route("/logs/level", get(get_level))
+}
+
+pub fn admin_routes() -> Router {
+    Router::new().route("/logs/level", put(set_level))
+}
+
+async fn logs(Query(query): Query<LogQuery>) -> impl IntoResponse {
+    let level = match query.level() {
+        Ok(level) => level,
+        Err(e) => return e.into_response(),
+    };
+
+    Json(Logs::query(
+        query.since.unwrap_or(0),
+        level,
+        query.target.as_deref(),
+        query.limit.unwrap_or(500),
+    ))
+    .into_response()
+}
+
+// Buffered records matching the query first, then new ones as they're logged
+async fn log_stream(Query(query): Query<LogQuery>) -> impl IntoResponse {
+    let level = match query.level() {
+        Ok(level) => level,
+        Err(e) => return e.into_response(),
+    };
+
+    let updates = BroadcastStream::new(Logs::subscribe());
+    let initial = Logs::query(
+        query.since.unwrap_or(0),
+        level,
+        query.target.as_deref(),
+        query.limit.unwrap_or(100),
+    );
+    let last_seq = initial.last().map(|record| record.seq);
+
+    let target = query.target;
+    let updates = updates.filter_map(move |update| {
+        update.ok().filter(|record| {
+            last_seq.is_none_or(|seq| record.seq > seq)
+                && Logs::matches(record, level, target.as_deref())
+        })
+    });
+
+    let stream = tokio_stream::iter(initial)
+        .chain(updates)
+        .map(|record| Ok::<_, Infallible>(Event::default().json_data(&record).unwrap()));
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn get_level() -> impl IntoResponse {
+    Json(json!({ "filter": Logs::filter() }))
+}
+
+#[derive(Deserialize)]
+struct LevelRequest {
+    // e.g. "info,safewalk::hazard_analyzer=debug"
+    filter: String,
+}
+
+async fn set_level(Json(request): Json<LevelRequest>) -> impl IntoResponse {
+    match Logs::set_filter(&request.filter) {
+        Ok(()) => Json(json!({ "filter": request.filter })).into_response(),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("{:#}", e) })),
+        )
+            .into_response(),
+    }
+}
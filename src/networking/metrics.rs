@@ -0,0 +1,313 @@
+use lazy_static::lazy_static;
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use sysinfo::System;
+use tokio::time::Instant;
+
+// f64 gauge stored as its bit pattern
+struct Gauge(AtomicU64);
+
+impl Gauge {
+    const fn new() -> Self {
+        Gauge(AtomicU64::new(0))
+    }
+
+    fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct DeviceMetrics {
+    started: Instant,
+    loop_seconds: Gauge,
+    loop_iterations: Counter,
+    loop_overruns: Counter,
+    gps_fix: Gauge,
+    gps_satellites: Gauge,
+    nmea_parse_errors: Counter,
+    hazards_in_range: Gauge,
+    // front, right, back, left
+    motor_duty: [Gauge; 4],
+    overpass_successes: Counter,
+    overpass_failures: Counter,
+}
+
+lazy_static! {
+    static ref METRICS: DeviceMetrics = DeviceMetrics {
+        started: Instant::now(),
+        loop_seconds: Gauge::new(),
+        loop_iterations: Counter::new(),
+        loop_overruns: Counter::new(),
+        gps_fix: Gauge::new(),
+        gps_satellites: Gauge::new(),
+        nmea_parse_errors: Counter::new(),
+        hazards_in_range: Gauge::new(),
+        motor_duty: [Gauge::new(), Gauge::new(), Gauge::new(), Gauge::new()],
+        overpass_successes: Counter::new(),
+        overpass_failures: Counter::new(),
+    };
+}
+
+const MOTOR_CHANNELS: [&str; 4] = ["front", "right", "back", "left"];
+
+pub struct Metrics;
+
+impl Metrics {
+    // Called first thing in main so uptime counts from process start rather
+    // than from the first metric update
+    pub fn start() {
+        lazy_static::initialize(&METRICS);
+    }
+
+    pub fn loop_completed(duration: Duration, overrun: bool) {
+        METRICS.loop_seconds.set(duration.as_secs_f64());
+        METRICS.loop_iterations.inc();
+        if overrun {
+            METRICS.loop_overruns.inc();
+        }
+    }
+
+    pub fn gps_fix(valid: bool, satellites: u8) {
+        METRICS.gps_fix.set(if valid { 1.0 } else { 0.0 });
+        METRICS.gps_satellites.set(satellites as f64);
+    }
+
+    pub fn nmea_parse_error() {
+        METRICS.nmea_parse_errors.inc();
+    }
+
+    pub fn hazards_in_range(count: usize) {
+        METRICS.hazards_in_range.set(count as f64);
+    }
+
+    // Same order as `VibrationSystemSpeeds::vec`
+    pub fn motor_duty(speeds: &[f64]) {
+        for (gauge, speed) in METRICS.motor_duty.iter().zip(speeds) {
+            gauge.set(*speed);
+        }
+    }
+
+    pub fn overpass_fetch(success: bool) {
+        if success {
+            METRICS.overpass_successes.inc();
+        } else {
+            METRICS.overpass_failures.inc();
+        }
+    }
+
+    // OpenMetrics text exposition, see https://openmetrics.io
+    pub fn render(system: &mut System) -> String {
+        system.refresh_memory();
+
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "safewalk_loop_duration_seconds",
+            "Duration of the last main loop iteration",
+            Some("seconds"),
+            METRICS.loop_seconds.get(),
+        );
+        counter(
+            &mut out,
+            "safewalk_loop_iterations",
+            "Main loop iterations",
+            METRICS.loop_iterations.get(),
+        );
+        counter(
+            &mut out,
+            "safewalk_loop_overruns",
+            "Main loop iterations longer than the 100 ms period",
+            METRICS.loop_overruns.get(),
+        );
+        gauge(
+            &mut out,
+            "safewalk_gps_fix",
+            "1 if the last RMC sentence had a valid fix",
+            None,
+            METRICS.gps_fix.get(),
+        );
+        gauge(
+            &mut out,
+            "safewalk_gps_satellites",
+            "Satellites used in the last GGA fix",
+            None,
+            METRICS.gps_satellites.get(),
+        );
+        counter(
+            &mut out,
+            "safewalk_nmea_parse_errors",
+            "NMEA sentences rejected for a bad checksum or format",
+            METRICS.nmea_parse_errors.get(),
+        );
+        gauge(
+            &mut out,
+            "safewalk_hazards_in_range",
+            "Hazards within detection range",
+            None,
+            METRICS.hazards_in_range.get(),
+        );
+
+        let _ = writeln!(out, "# TYPE safewalk_motor_duty gauge");
+        let _ = writeln!(
+            out,
+            "# HELP safewalk_motor_duty Vibration motor duty cycle 0-1"
+        );
+        for (channel, duty) in MOTOR_CHANNELS.iter().zip(&METRICS.motor_duty) {
+            let _ = writeln!(
+                out,
+                "safewalk_motor_duty{{channel=\"{}\"}} {}",
+                channel,
+                duty.get()
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE safewalk_overpass_fetches counter");
+        let _ = writeln!(
+            out,
+            "# HELP safewalk_overpass_fetches Overpass API requests by result"
+        );
+        let _ = writeln!(
+            out,
+            "safewalk_overpass_fetches_total{{result=\"success\"}} {}",
+            METRICS.overpass_successes.get()
+        );
+        let _ = writeln!(
+            out,
+            "safewalk_overpass_fetches_total{{result=\"failure\"}} {}",
+            METRICS.overpass_failures.get()
+        );
+
+        if let Some(temperature) = cpu_temperature() {
+            gauge(
+                &mut out,
+                "safewalk_cpu_temperature_celsius",
+                "SoC temperature",
+                Some("celsius"),
+                temperature,
+            );
+        }
+        gauge(
+            &mut out,
+            "safewalk_memory_available_bytes",
+            "Available system memory",
+            Some("bytes"),
+            system.available_memory() as f64,
+        );
+        gauge(
+            &mut out,
+            "safewalk_memory_total_bytes",
+            "Total system memory",
+            Some("bytes"),
+            system.total_memory() as f64,
+        );
+        gauge(
+            &mut out,
+            "safewalk_uptime_seconds",
+            "Time since SafeWalk started",
+            Some("seconds"),
+            METRICS.started.elapsed().as_secs_f64(),
+        );
+        gauge(
+            &mut out,
+            "safewalk_system_uptime_seconds",
+            "Time since the system booted",
+            Some("seconds"),
+            System::uptime() as f64,
+        );
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, unit: Option<&str>, value: f64) {
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    if let Some(unit) = unit {
+        let _ = writeln!(out, "# UNIT {} {}", name, unit);
+    }
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "{}_total {}", name, value);
+}
+
+fn cpu_temperature() -> Option<f64> {
+    let millidegrees = fs::read_to_string("/sys/class/thermal/thermal_zone0/temp").ok()?;
+
+    Some(millidegrees.trim().parse::<f64>().ok()? / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::Metrics;
+    use sysinfo::System;
+
+    #[test]
+    fn openmetrics_exposition() {
+        Metrics::nmea_parse_error();
+        let out = Metrics::render(&mut System::new());
+        let lines = out.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.last(), Some(&"# EOF"));
+        assert_eq!(lines.iter().filter(|line| **line == "# EOF").count(), 1);
+
+        // Every family has its type, then help, then samples
+        for (i, line) in lines.iter().enumerate() {
+            let Some(rest) = line.strip_prefix("# TYPE ") else {
+                continue;
+            };
+            let (name, kind) = rest.split_once(' ').unwrap();
+            let help = lines[i + 1..]
+                .iter()
+                .find(|line| !line.starts_with("# UNIT "))
+                .unwrap();
+            assert!(help.starts_with(&format!("# HELP {} ", name)), "{}", help);
+
+            let samples = lines[i + 1..]
+                .iter()
+                .take_while(|line| !line.starts_with("# TYPE ") && **line != "# EOF")
+                .filter(|line| !line.starts_with('#'))
+                .collect::<Vec<_>>();
+            assert!(!samples.is_empty(), "{} has no samples", name);
+            if kind == "counter" {
+                let total = format!("{}_total", name);
+                assert!(samples.iter().all(|sample| sample.starts_with(&total)), "{:?}", samples);
+            }
+        }
+
+        assert!(out.contains("# TYPE safewalk_loop_iterations counter\n"));
+        assert!(out.contains("# UNIT safewalk_uptime_seconds seconds\n"));
+        let errors = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("safewalk_nmea_parse_errors_total "))
+            .unwrap();
+        assert!(errors.parse::<u64>().unwrap() >= 1);
+    }
+}
@@ -1,5 +1,18 @@
+mod auth;
+mod control;
+mod frontend;
+mod geojson;
+mod history;
+mod logs;
+mod metrics;
+mod schema;
 mod server;
+mod tiles;
+mod tls;
 
+pub use control::{Control, ControlCommand};
+pub use geojson::MapData;
+pub use metrics::Metrics;
 pub use server::*;
 
 use tokio::process::Command;
@@ -0,0 +1,131 @@
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Number,
+    String,
+    Array,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct KeySchema {
+    pub key: &'static str,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    pub unit: Option<&'static str>,
+    pub description: &'static str,
+}
+
+// Keys published by SafeWalk itself. Clients may still write other keys.
+pub const TELEMETRY_SCHEMA: &[KeySchema] = &[
+    KeySchema {
+        key: "latitude",
+        value_type: ValueType::Number,
+        unit: Some("deg"),
+        description: "Current latitude (WGS84)",
+    },
+    KeySchema {
+        key: "longitude",
+        value_type: ValueType::Number,
+        unit: Some("deg"),
+        description: "Current longitude (WGS84)",
+    },
+    KeySchema {
+        key: "heading",
+        value_type: ValueType::Number,
+        unit: Some("rad"),
+        description: "Direction of travel, counter-clockwise from east",
+    },
+    KeySchema {
+        key: "heading_accuracy",
+        value_type: ValueType::Number,
+        unit: Some("rad"),
+        description: "One standard deviation of the GPS course; null while standing still",
+    },
+    KeySchema {
+        key: "compass_heading",
+        value_type: ValueType::Number,
+        unit: Some("rad"),
+        description: "Tilt-compensated compass heading before fusion, counter-clockwise from east",
+    },
+    KeySchema {
+        key: "speed",
+        value_type: ValueType::Number,
+        unit: Some("m/s"),
+        description: "Smoothed walking speed",
+    },
+    KeySchema {
+        key: "position_accuracy",
+        value_type: ValueType::Number,
+        unit: Some("m"),
+        description: "Root mean square error of the smoothed position",
+    },
+    KeySchema {
+        key: "fix_mode",
+        value_type: ValueType::String,
+        unit: None,
+        description: "GPS fix quality: good, degraded or lost",
+    },
+    KeySchema {
+        key: "fix_age",
+        value_type: ValueType::Number,
+        unit: Some("s"),
+        description: "Time since the last valid fix; null before the first",
+    },
+    KeySchema {
+        key: "local_time",
+        value_type: ValueType::String,
+        unit: None,
+        description: "GNSS time of the last fix in the configured time zone, RFC 3339",
+    },
+    KeySchema {
+        key: "satellites",
+        value_type: ValueType::Number,
+        unit: None,
+        description: "Satellites used in the last fix",
+    },
+    KeySchema {
+        key: "hdop",
+        value_type: ValueType::Number,
+        unit: None,
+        description: "Horizontal dilution of precision of the last fix; null if not reported",
+    },
+    KeySchema {
+        key: "hazards",
+        value_type: ValueType::Array,
+        unit: None,
+        description: "Hazard reports in range, nearest first",
+    },
+    KeySchema {
+        key: "hazard_distance",
+        value_type: ValueType::Number,
+        unit: Some("m"),
+        description: "Distance to the nearest hazard",
+    },
+    KeySchema {
+        key: "speeds",
+        value_type: ValueType::Array,
+        unit: Some("duty"),
+        description: "Motor intensities 0-1 as [front, right, back, left]",
+    },
+    KeySchema {
+        key: "destination",
+        value_type: ValueType::Array,
+        unit: Some("deg"),
+        description: "Destination set from the dashboard as [latitude, longitude]",
+    },
+    KeySchema {
+        key: "destination_distance",
+        value_type: ValueType::Number,
+        unit: Some("m"),
+        description: "Straight-line distance to the destination",
+    },
+];
+
+pub fn unit_for(key: &str) -> Option<&'static str> {
+    TELEMETRY_SCHEMA
+        .iter()
+        .find(|schema| schema.key == key)
+        .and_then(|schema| schema.unit)
+}
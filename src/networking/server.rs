@@ -1,147 +1,248 @@
-use axum::body::Body;
-use axum::http::{HeaderValue, Response, StatusCode, header};
+use crate::networking::auth::{AuthConfig, Role, require_role};
+use crate::networking::control::Control;
+use crate::networking::frontend;
+use crate::networking::geojson::MapData;
+use crate::networking::{logs, tiles};
+use crate::networking::history::{HISTORY_CAPACITY, History, charted, downsample};
+use crate::networking::metrics::Metrics;
+use crate::networking::schema::{KeySchema, TELEMETRY_SCHEMA, unit_for};
+use crate::networking::tls::{self, TlsConfig};
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware;
 use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::{
     Router,
-    extract::{Extension, Json, Path},
-    routing::{get, post},
+    extract::{Extension, Json, Path, Query},
+    routing::{get, post, put},
 };
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
-use log::info;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{info, warn};
 use sysinfo::System;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio::sync::{Mutex, RwLock};
-use tower_http::cors::CorsLayer;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Mutex, RwLock, broadcast};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TelemetryValue {
+    value: Value,
+    #[serde(default)]
+    unit: Option<String>,
+    // Milliseconds since the Unix epoch
+    #[serde(default)]
+    timestamp: u64,
+}
 
+// A single key's value as sent over the streams and accepted by POST /telemetry
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TelemetryData {
     key: String,
-    value: String,
+    #[serde(flatten)]
+    value: TelemetryValue,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub port: u16,
+    // Origins allowed to call the API from another site, e.g.
+    // "http://localhost:3001" for the frontend dev server. "*" allows any.
+    pub cors_origins: Vec<String>,
+    // Built dashboard to serve; ignored when built with `embed-frontend`
+    pub frontend_dir: PathBuf,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            cors_origins: Vec::new(),
+            frontend_dir: PathBuf::from("/home/pi/frontend"),
+            auth: AuthConfig::default(),
+            tls: TlsConfig::default(),
+        }
+    }
 }
 
 pub struct AppState {
-    telemetry_data: RwLock<Vec<TelemetryData>>,
+    telemetry_data: RwLock<BTreeMap<String, TelemetryValue>>,
+    history: RwLock<HashMap<String, History>>,
 }
 
 lazy_static! {
     static ref TELEMETRY_STATE: Arc<Mutex<AppState>> = Arc::new(Mutex::new(AppState {
-        telemetry_data: RwLock::new(vec![]),
+        telemetry_data: RwLock::new(BTreeMap::new()),
+        history: RwLock::new(HashMap::new()),
     }));
 
     static ref SYSTEM_STATS: Arc<Mutex<System>> = Arc::new(Mutex::new(System::new()));
+
+    // Every changed value is published here for the WebSocket and SSE streams
+    static ref TELEMETRY_UPDATES: broadcast::Sender<TelemetryData> = broadcast::channel(256).0;
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Inserts or replaces a value and records charted keys in their history.
+// Stream subscribers are only notified when it changed. The unit defaults to
+// the one in the schema for known keys.
+async fn upsert(state: &AppState, key: &str, value: Value, unit: Option<String>) {
+    let mut telemetry_data = state.telemetry_data.write().await;
+
+    let changed = telemetry_data.get(key).is_none_or(|existing| existing.value != value);
+
+    let value = TelemetryValue {
+        value,
+        unit: unit.or_else(|| unit_for(key).map(|u| u.to_string())),
+        timestamp: now_ms(),
+    };
+
+    if charted(key, &value.value) {
+        state
+            .history
+            .write()
+            .await
+            .entry(key.to_string())
+            .or_insert_with(|| History::new(HISTORY_CAPACITY))
+            .record(value.timestamp, value.value.clone());
+    }
+
+    telemetry_data.insert(key.to_string(), value.clone());
+    if changed {
+        let _ = TELEMETRY_UPDATES.send(TelemetryData {
+            key: key.to_string(),
+            value,
+        });
+    }
 }
 
 pub struct Telemetry;
 
 impl Telemetry {
-    pub fn init(port: u16) {
-        let app = Router::new()
+    pub async fn init(config: ServerConfig) -> Result<()> {
+        let auth = Arc::new(config.auth);
+
+        let public = Router::new()
             .route("/status", get(status_check))
-            .route("/telemetry", post(update_telemetry).get(get_telemetry))
-            .route("/", get(frontend))
-            .route("/{*wildcard}", get(frontend))
-            .route(
-                "/telemetry/{key}",
-                get(get_telemetry_value).put(set_telemetry_value),
-            )
+            .merge(frontend::routes(config.frontend_dir));
+
+        let read_only = Router::new()
+            .route("/telemetry", get(get_telemetry))
+            .route("/telemetry/ws", get(telemetry_ws))
+            .route("/telemetry/stream", get(telemetry_stream))
+            .route("/telemetry/schema", get(telemetry_schema))
+            .route("/telemetry/{key}", get(get_telemetry_value))
+            .route("/telemetry/{key}/history", get(get_telemetry_history))
             .route("/health", get(system_health))
+            .route("/metrics", get(metrics))
+            .merge(MapData::routes())
+            .merge(tiles::routes())
+            .merge(logs::read_routes())
+            .route_layer(middleware::from_fn_with_state(
+                (auth.clone(), Role::ReadOnly),
+                require_role,
+            ));
+
+        let admin = Router::new()
+            .route("/telemetry", post(update_telemetry))
+            .route("/telemetry/{key}", put(set_telemetry_value))
+            .merge(Control::routes())
+            .merge(logs::admin_routes())
+            .route_layer(middleware::from_fn_with_state(
+                (auth.clone(), Role::Admin),
+                require_role,
+            ));
+
+        let app = public
+            .merge(read_only)
+            .merge(admin)
             .layer(Extension(TELEMETRY_STATE.clone()))
-            .layer(CorsLayer::very_permissive());
+            .layer(cors(&config.cors_origins));
 
-        let addr = SocketAddr::from(([0, 0, 0, 0], port));
-        info!("Listening on {}", addr);
+        let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
 
-        tokio::spawn(async move {
-            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-            axum::serve(listener, app).await.unwrap();
-        });
-    }
-
-    pub async fn put_number(key: &str, value: f64) {
-        Self::put_string(key, value.to_string()).await;
-    }
+        if config.tls.enabled {
+            let rustls = tls::load(&config.tls).await?;
+            info!("Listening on https://{}", addr);
 
-    pub async fn put_string(key: &str, value: String) {
-        let state = TELEMETRY_STATE.lock().await;
-        let mut telemetry_data = state.telemetry_data.write().await;
-
-        if let Some(existing) = telemetry_data.iter_mut().find(|data| data.key == key) {
-            existing.value = value;
+            tokio::spawn(async move {
+                axum_server::bind_rustls(addr, rustls)
+                    .serve(app.into_make_service())
+                    .await
+                    .unwrap();
+            });
         } else {
-            telemetry_data.push(TelemetryData {
-                key: key.to_string(),
-                value,
+            info!("Listening on http://{}", addr);
+
+            tokio::spawn(async move {
+                let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                axum::serve(listener, app).await.unwrap();
             });
         }
+
+        Ok(())
     }
 
-    pub async fn put_vec<T: Serialize>(key: &str, values: Vec<T>) {
-        let json_values = serde_json::to_string(&values).unwrap();
+    pub async fn put<T: Serialize + ?Sized>(key: &str, value: &T) {
+        let value = serde_json::to_value(value).unwrap();
         let state = TELEMETRY_STATE.lock().await;
-        let mut telemetry_data = state.telemetry_data.write().await;
 
-        if let Some(existing) = telemetry_data.iter_mut().find(|data| data.key == key) {
-            existing.value = json_values;
-        } else {
-            telemetry_data.push(TelemetryData {
-                key: key.to_string(),
-                value: json_values,
-            });
-        }
+        upsert(&state, key, value, None).await;
     }
 
-    pub async fn get(key: &str) -> Option<String> {
+    pub async fn get(key: &str) -> Option<Value> {
         let state = TELEMETRY_STATE.lock().await;
         let telemetry_data = state.telemetry_data.read().await;
 
-        telemetry_data
-            .iter()
-            .find(|data| data.key == key)
-            .map(|data| data.value.clone())
+        telemetry_data.get(key).map(|data| data.value.clone())
     }
 }
 
-async fn status_check() -> impl IntoResponse {
-    "OK"
-}
-
-async fn frontend(Path(path): Path<Vec<String>>) -> impl IntoResponse {
-    let mut path = path.join("/");
-    let mut path = path.trim_start_matches('/');
-
-    if path.is_empty() {
-        path = "index.html";
+// Without configured origins only same-origin requests (the bundled frontend)
+// can use the API from a browser
+fn cors(origins: &[String]) -> CorsLayer {
+    if origins.iter().any(|origin| origin == "*") {
+        return CorsLayer::very_permissive();
     }
 
-    let mime_type = mime_guess::from_path(path.clone()).first_or_text_plain();
-
-    let dir = PathBuf::from(format!("/home/pi/frontend/{}", path));
-
-    match File::open(dir.clone()).await {
-        Err(_) => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap(),
-        Ok(mut file) => {
-            let mut contents = String::new();
-            file.read_to_string(&mut contents).await.unwrap();
-
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_str(mime_type.as_ref()).unwrap(),
-                )
-                .body(Body::from(contents))
-                .unwrap()
-        }
-    }
+    let origins = origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Ignoring invalid CORS origin {}", origin);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+}
+
+async fn status_check() -> impl IntoResponse {
+    "OK"
 }
 
 async fn update_telemetry(
@@ -149,33 +250,74 @@ async fn update_telemetry(
     Json(payload): Json<TelemetryData>,
 ) -> String {
     let state = state.lock().await;
-    state.telemetry_data.write().await.push(payload);
+
+    upsert(&state, &payload.key, payload.value.value, payload.value.unit).await;
     json!({"status": "success"}).to_string()
 }
 
 async fn get_telemetry(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
-) -> Json<Vec<TelemetryData>> {
+) -> Json<BTreeMap<String, TelemetryValue>> {
     let state = state.lock().await;
     let telemetry_data = state.telemetry_data.read().await.clone();
     Json(telemetry_data)
 }
 
+async fn telemetry_schema() -> Json<&'static [KeySchema]> {
+    Json(TELEMETRY_SCHEMA)
+}
+
+// Returns the bare value, or null for unknown keys
 async fn get_telemetry_value(
     Extension(state): Extension<Arc<Mutex<AppState>>>,
     Path(key): Path<String>,
-) -> impl IntoResponse {
+) -> Json<Value> {
     let state = state.lock().await;
     let telemetry_data = state.telemetry_data.read().await;
 
-    match telemetry_data.iter().find(|data| data.key == key) {
-        Some(data) => data.clone().value.into_response(),
-        None => Response::builder()
-            .status(StatusCode::OK)
-            .body(Body::new("null".into_response()))
-            .unwrap()
-            .into_response(),
-    }
+    Json(
+        telemetry_data
+            .get(&key)
+            .map(|data| data.value.clone())
+            .unwrap_or(Value::Null),
+    )
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    // Milliseconds since the Unix epoch
+    since: Option<u64>,
+    // Maximum number of points; longer ranges are averaged down to this
+    limit: Option<usize>,
+}
+
+async fn get_telemetry_history(
+    Extension(state): Extension<Arc<Mutex<AppState>>>,
+    Path(key): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> impl IntoResponse {
+    let state = state.lock().await;
+    let history = state.history.read().await;
+
+    let Some(samples) = history.get(&key) else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "unknown key"}))).into_response();
+    };
+
+    let samples = samples.since(query.since.unwrap_or(0));
+    let points = downsample(samples, query.limit.unwrap_or(500));
+    let unit = state
+        .telemetry_data
+        .read()
+        .await
+        .get(&key)
+        .and_then(|data| data.unit.clone());
+
+    Json(json!({
+        "key": key,
+        "unit": unit,
+        "points": points,
+    }))
+    .into_response()
 }
 
 async fn set_telemetry_value(
@@ -184,18 +326,8 @@ async fn set_telemetry_value(
     Json(payload): Json<Value>,
 ) -> impl IntoResponse {
     let state = state.lock().await;
-    let mut telemetry_data = state.telemetry_data.write().await;
-
-    let value = payload.to_string();
 
-    if let Some(existing) = telemetry_data.iter_mut().find(|data| data.key == key) {
-        existing.value = value;
-    } else {
-        telemetry_data.push(TelemetryData {
-            key: key.clone(),
-            value,
-        });
-    }
+    upsert(&state, &key, payload, None).await;
 
     json!({"status": "success"}).to_string().into_response()
 }
@@ -213,3 +345,129 @@ async fn system_health() -> impl IntoResponse {
         "available_memory": available_memory,
     }).to_string().into_response()
 }
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    // Comma separated keys, e.g. ?topics=latitude,longitude. All keys if absent.
+    topics: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Subscription {
+    topics: Option<Vec<String>>,
+}
+
+struct TopicFilter(Option<HashSet<String>>);
+
+impl TopicFilter {
+    fn parse(topics: Option<&str>) -> Self {
+        TopicFilter(topics.map(|t| {
+            t.split(',')
+                .map(|topic| topic.trim().to_string())
+                .filter(|topic| !topic.is_empty())
+                .collect()
+        }))
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.0.as_ref().is_none_or(|topics| topics.contains(key))
+    }
+}
+
+async fn snapshot(filter: &TopicFilter) -> Vec<TelemetryData> {
+    let state = TELEMETRY_STATE.lock().await;
+    let telemetry_data = state.telemetry_data.read().await;
+
+    telemetry_data
+        .iter()
+        .filter(|(key, _)| filter.matches(key))
+        .map(|(key, value)| TelemetryData {
+            key: key.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+// Server-Sent Events: the current values first, then every change
+async fn telemetry_stream(
+    Query(query): Query<StreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let filter = TopicFilter::parse(query.topics.as_deref());
+
+    // Subscribe before taking the snapshot so no update falls in between
+    let updates = BroadcastStream::new(TELEMETRY_UPDATES.subscribe());
+    let initial = tokio_stream::iter(snapshot(&filter).await);
+
+    let updates = updates.filter_map(move |update| update.ok().filter(|data| filter.matches(&data.key)));
+
+    let stream = initial
+        .chain(updates)
+        .map(|data| Ok(Event::default().json_data(&data).unwrap()));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+// WebSocket: same messages as the SSE stream. Clients can change their topics
+// at any time by sending {"topics": ["heading", ...]}, or {"topics": null} for all.
+async fn telemetry_ws(ws: WebSocketUpgrade, Query(query): Query<StreamQuery>) -> impl IntoResponse {
+    let filter = TopicFilter::parse(query.topics.as_deref());
+
+    ws.on_upgrade(move |socket| telemetry_socket(socket, filter))
+}
+
+async fn telemetry_socket(mut socket: WebSocket, mut filter: TopicFilter) {
+    let mut updates = TELEMETRY_UPDATES.subscribe();
+
+    for data in snapshot(&filter).await {
+        if send_update(&mut socket, &data).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(data) => {
+                    if filter.matches(&data.key) && send_update(&mut socket, &data).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => warn!("Telemetry socket skipped {} updates", skipped),
+                Err(RecvError::Closed) => return,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(subscription) = serde_json::from_str::<Subscription>(&text) {
+                        filter = TopicFilter(subscription.topics.map(|t| t.into_iter().collect()));
+
+                        for data in snapshot(&filter).await {
+                            if send_update(&mut socket, &data).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
+
+async fn send_update(socket: &mut WebSocket, data: &TelemetryData) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(data).unwrap();
+
+    socket.send(Message::Text(text.into())).await
+}
+
+async fn metrics() -> impl IntoResponse {
+    let mut stats = SYSTEM_STATS.lock().await;
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        Metrics::render(&mut stats),
+    )
+}
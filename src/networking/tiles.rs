@@ -0,0 +1,319 @@
+use crate::hazard_analyzer::HazardKind;
+use crate::networking::geojson::{map_state, segment_intersects};
+use crate::overpass::{Element, Point};
+use axum::Router;
+use axum::extract::{Json, Path};
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use serde_json::json;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// Mapbox Vector Tiles (https://github.com/mapbox/vector-tile-spec, v2) of the
+// cached OSM data, so the dashboard has a map without internet access. The
+// protobuf is written by hand; only the handful of fields we need exist.
+
+const EXTENT: u32 = 4096;
+// Features within this fraction of a tile outside it are kept, so lines and
+// points on tile edges aren't cut off
+const BUFFER: f64 = 1.0 / 16.0;
+const MAX_ZOOM: u8 = 22;
+// Vertices further out than this, in tile coordinates, are pulled in. Keeps
+// coordinates and their deltas well inside i32; lines bend only where
+// they're 256 tiles away.
+const MAX_COORDINATE: f64 = (EXTENT * 256) as f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GeomType {
+    Point = 1,
+    LineString = 2,
+}
+
+// Web Mercator position in tile units at zoom `z`
+fn project(point: &Point, z: u8) -> (f64, f64) {
+    let n = f64::from(1u32 << z);
+    let lat = point.lat.clamp(-85.0511, 85.0511).to_radians();
+
+    let x = (point.lon + 180.0) / 360.0 * n;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n;
+
+    (x, y)
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn key(out: &mut Vec<u8>, field: u32, wire_type: u32) {
+    varint(out, u64::from((field << 3) | wire_type));
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    key(out, field, 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn packed_field(out: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::new();
+    for value in values {
+        varint(&mut packed, u64::from(*value));
+    }
+    bytes_field(out, field, &packed);
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+// Geometry commands for points in tile coordinates (0..EXTENT)
+fn encode_geometry(geom_type: GeomType, points: &[(i32, i32)]) -> Vec<u32> {
+    let mut out = Vec::new();
+    let mut cursor = (0, 0);
+
+    let move_to = |out: &mut Vec<u32>, cursor: &mut (i32, i32), (x, y): (i32, i32)| {
+        out.push(zigzag(x - cursor.0));
+        out.push(zigzag(y - cursor.1));
+        *cursor = (x, y);
+    };
+
+    match geom_type {
+        GeomType::Point => {
+            out.push(command(1, points.len() as u32));
+            for point in points {
+                move_to(&mut out, &mut cursor, *point);
+            }
+        }
+        GeomType::LineString => {
+            out.push(command(1, 1));
+            move_to(&mut out, &mut cursor, points[0]);
+            out.push(command(2, points.len() as u32 - 1));
+            for point in &points[1..] {
+                move_to(&mut out, &mut cursor, *point);
+            }
+        }
+    }
+
+    out
+}
+
+struct Feature {
+    id: u64,
+    geom_type: GeomType,
+    points: Vec<(i32, i32)>,
+    properties: Vec<(&'static str, String)>,
+}
+
+// One layer; keys and values are de-duplicated as the spec intends
+fn encode_layer(name: &str, features: &[Feature]) -> Vec<u8> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut values: Vec<&str> = Vec::new();
+    let mut key_index = HashMap::new();
+    let mut value_index = HashMap::new();
+
+    let mut layer = Vec::new();
+    key(&mut layer, 15, 0);
+    varint(&mut layer, 2);
+    bytes_field(&mut layer, 1, name.as_bytes());
+
+    for feature in features {
+        let mut tags = Vec::new();
+        for (k, v) in &feature.properties {
+            let k = *key_index.entry(*k).or_insert_with(|| {
+                keys.push(k);
+                keys.len() as u32 - 1
+            });
+            let v = *value_index.entry(v.as_str()).or_insert_with(|| {
+                values.push(v);
+                values.len() as u32 - 1
+            });
+            tags.extend([k, v]);
+        }
+
+        let mut encoded = Vec::new();
+        key(&mut encoded, 1, 0);
+        varint(&mut encoded, feature.id);
+        packed_field(&mut encoded, 2, &tags);
+        key(&mut encoded, 3, 0);
+        varint(&mut encoded, feature.geom_type as u64);
+        packed_field(
+            &mut encoded,
+            4,
+            &encode_geometry(feature.geom_type, &feature.points),
+        );
+
+        bytes_field(&mut layer, 2, &encoded);
+    }
+
+    for k in keys {
+        bytes_field(&mut layer, 3, k.as_bytes());
+    }
+    for v in values {
+        // Value message with only string_value set
+        let mut value = Vec::new();
+        bytes_field(&mut value, 1, v.as_bytes());
+        bytes_field(&mut layer, 4, &value);
+    }
+
+    key(&mut layer, 5, 0);
+    varint(&mut layer, u64::from(EXTENT));
+
+    layer
+}
+
+fn feature(element: &Element, z: u8, x: u32, y: u32) -> Option<Feature> {
+    let (geom_type, points) = match element {
+        Element::Node { .. } => (GeomType::Point, element.location()?),
+        Element::Way { .. } => (GeomType::LineString, element.location()?),
+        Element::Relation { .. } => return None,
+    };
+
+    let tile = points
+        .iter()
+        .map(|point| {
+            let (px, py) = project(point, z);
+            (px - f64::from(x), py - f64::from(y))
+        })
+        .collect::<Vec<_>>();
+
+    // Lines count when any segment crosses the tile, even with no vertex in it
+    let (min, max) = ((-BUFFER, -BUFFER), (1.0 + BUFFER, 1.0 + BUFFER));
+    let inside = match geom_type {
+        GeomType::Point => tile.iter().any(|point| segment_intersects(*point, *point, min, max)),
+        GeomType::LineString => tile.windows(2).any(|w| segment_intersects(w[0], w[1], min, max)),
+    };
+    if !inside {
+        return None;
+    }
+
+    let mut properties = vec![(
+        "kind",
+        json!(HazardKind::classify(element))
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    )];
+    for tag in ["highway", "name"] {
+        if let Some(value) = element.tags().get(tag) {
+            properties.push((tag, value.clone()));
+        }
+    }
+
+    Some(Feature {
+        id: element.id(),
+        geom_type,
+        points: tile
+            .iter()
+            .map(|(px, py)| {
+                let scale = |p: f64| {
+                    (p * f64::from(EXTENT)).round().clamp(-MAX_COORDINATE, MAX_COORDINATE) as i32
+                };
+                (scale(*px), scale(*py))
+            })
+            .collect(),
+        properties,
+    })
+}
+
+// A tile with a single "osm" layer of the loaded Overpass elements
+pub fn encode_tile(elements: &[Element], z: u8, x: u32, y: u32) -> Vec<u8> {
+    let features = elements
+        .iter()
+        .filter_map(|element| feature(element, z, x, y))
+        .collect::<Vec<_>>();
+
+    let mut tile = Vec::new();
+    if !features.is_empty() {
+        bytes_field(&mut tile, 3, &encode_layer("osm", &features));
+    }
+
+    tile
+}
+
+pub fn routes() -> Router {
+    Router::new().route("/tiles/{z}/{x}/{y}", get(tile))
+}
+
+// /tiles/{z}/{x}/{y}.mvt
+async fn tile(Path((z, x, y)): Path<(u8, u32, String)>) -> impl IntoResponse {
+    let Some(y) = y.strip_suffix(".mvt").and_then(|y| y.parse::<u32>().ok()) else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "expected {z}/{x}/{y}.mvt"})))
+            .into_response();
+    };
+
+    if z > MAX_ZOOM || x >= 1 << z || y >= 1 << z {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "tile out of range"}))).into_response();
+    }
+
+    let state = map_state().await;
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.mapbox-vector-tile"),
+            // Elements change when Overpass data is refreshed
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        encode_tile(&state.elements, z, x, y),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::networking::tiles::{GeomType, encode_geometry, encode_tile, project};
+    use crate::overpass::{Element, OverpassBounds, Point};
+    use std::collections::HashMap;
+
+    #[test]
+    fn geometry_and_projection() {
+        // Examples from the vector tile spec
+        assert_eq!(encode_geometry(GeomType::Point, &[(25, 17)]), vec![9, 50, 34]);
+        assert_eq!(
+            encode_geometry(GeomType::LineString, &[(2, 2), (2, 10), (10, 10)]),
+            vec![9, 4, 4, 18, 0, 16, 16, 0]
+        );
+
+        let (x, y) = project(&Point { lat: 0.0, lon: 0.0 }, 1);
+        assert!((x - 1.0).abs() < 1e-9 && (y - 1.0).abs() < 1e-9);
+
+        let node = Element::Node {
+            id: 1,
+            lat: 33.4235,
+            lon: -111.9328,
+            tags: HashMap::new(),
+        };
+        let (x, y) = project(&Point { lat: 33.4235, lon: -111.9328 }, 16);
+        assert!(!encode_tile(std::slice::from_ref(&node), 16, x as u32, y as u32).is_empty());
+        assert!(encode_tile(&[node], 16, x as u32 + 2, y as u32).is_empty());
+
+        // A street through the tile with both ends far outside it
+        let street = Element::Way {
+            bounds: OverpassBounds {
+                max_lat: 0.0,
+                max_lon: 0.0,
+                min_lat: 0.0,
+                min_lon: 0.0,
+            },
+            geometry: vec![
+                Point { lat: 33.4235, lon: -112.0 },
+                Point { lat: 33.4235, lon: -111.8 },
+            ],
+            id: 2,
+            nodes: None,
+            tags: HashMap::new(),
+        };
+        let (x, y) = project(&Point { lat: 33.4235, lon: -111.9328 }, 20);
+        let (x, y) = (x as u32, y as u32);
+        assert!(!encode_tile(std::slice::from_ref(&street), 20, x, y).is_empty());
+        assert!(encode_tile(&[street], 20, x, y + 2).is_empty());
+    }
+}
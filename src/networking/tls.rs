@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    // PEM files; a self-signed pair is generated here on first boot
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_path: PathBuf::from("tls/cert.pem"),
+            key_path: PathBuf::from("tls/key.pem"),
+        }
+    }
+}
+
+// Names the device is reachable under on its own access point
+const SUBJECT_ALT_NAMES: &[&str] = &["safewalk.local", "localhost", "10.0.0.1"];
+
+fn generate_self_signed(config: &TlsConfig) -> Result<()> {
+    let names = SUBJECT_ALT_NAMES.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let certified = rcgen::generate_simple_self_signed(names)?;
+
+    for path in [&config.cert_path, &config.key_path] {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    fs::write(&config.cert_path, certified.cert.pem())?;
+    fs::write(&config.key_path, certified.key_pair.serialize_pem())?;
+
+    info!("Generated self-signed certificate {}", config.cert_path.display());
+    Ok(())
+}
+
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig> {
+    // Only the ring backend is compiled in; ignore the error if it's already set
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    if !config.cert_path.exists() || !config.key_path.exists() {
+        generate_self_signed(config).context("Failed to generate TLS certificate")?;
+    }
+
+    RustlsConfig::from_pem_file(&config.cert_path, &config.key_path)
+        .await
+        .context("Failed to load TLS certificate")
+}
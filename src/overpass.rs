@@ -1,4 +1,5 @@
 use crate::bbox;
+use crate::networking::Metrics;
 use anyhow::{Error, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -58,6 +59,14 @@ impl Element {
         }
     }
 
+    pub fn id(&self) -> u64 {
+        match self {
+            Element::Node { id, .. } => *id,
+            Element::Way { id, .. } => *id,
+            Element::Relation { id, .. } => *id,
+        }
+    }
+
     pub fn tags(&self) -> &HashMap<String, String> {
         match self {
             Element::Node { tags, .. } => tags,
@@ -82,6 +91,19 @@ pub struct Point {
     pub lon: f64,
 }
 
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+impl Point {
+    // Equirectangular approximation, plenty accurate at walking distances
+    pub fn distance_m(&self, other: &Point) -> f64 {
+        let mean_lat = ((self.lat + other.lat) / 2.0).to_radians();
+        let x = (other.lon - self.lon).to_radians() * mean_lat.cos();
+        let y = (other.lat - self.lat).to_radians();
+
+        (x * x + y * y).sqrt() * EARTH_RADIUS_M
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Copy, Clone)]
 pub struct OverpassBounds {
     #[serde(rename = "maxlat")]
@@ -95,6 +117,13 @@ pub struct OverpassBounds {
 }
 
 pub async fn fetch(bbox: [Point; 2]) -> Result<OverpassResponse, Error> {
+    let result = fetch_overpass(bbox).await;
+    Metrics::overpass_fetch(result.is_ok());
+
+    result
+}
+
+async fn fetch_overpass(bbox: [Point; 2]) -> Result<OverpassResponse, Error> {
     let overpass_url = "https://overpass-api.de/api/interpreter";
 
     let bbox_str = bbox
@@ -0,0 +1,109 @@
+use crate::gps::GNRMC;
+use crate::overpass::Point;
+use crate::position::{Pacer, PositionSource, ReplayOptions};
+use crate::util::BoxFuture;
+use anyhow::{Context, Result, bail};
+use chrono::DateTime;
+use log::info;
+use std::fs;
+
+struct TrackPoint {
+    point: Point,
+    // Seconds since the Unix epoch
+    time: Option<f64>,
+}
+
+// Replays the track points of a GPX file, e.g. one exported from a phone
+// tracking app. Points without a <time> are replayed one second apart.
+pub struct GpxReplay {
+    points: std::vec::IntoIter<TrackPoint>,
+    pacer: Pacer,
+    // Stand-in clock for untimed points
+    index: f64,
+}
+
+impl GpxReplay {
+    pub fn open(options: &ReplayOptions) -> Result<Self> {
+        let data = fs::read_to_string(&options.path)
+            .with_context(|| format!("Failed to read GPX file {}", options.path))?;
+
+        Self::new(&data, options.speed).with_context(|| format!("Invalid GPX file {}", options.path))
+    }
+
+    pub fn new(data: &str, speed: f64) -> Result<Self> {
+        let points = parse_track(data)?;
+        info!("Replaying {} GPX track points", points.len());
+
+        Ok(Self {
+            points: points.into_iter(),
+            pacer: Pacer::new(speed),
+            index: 0.0,
+        })
+    }
+}
+
+// Value of `name="..."` or `name='...'` in a tag's attributes
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['"', '\''] {
+        let pattern = format!(" {}={}", name, quote);
+        if let Some(start) = tag.find(&pattern) {
+            let rest = &tag[start + pattern.len()..];
+            return rest.find(quote).map(|end| &rest[..end]);
+        }
+    }
+
+    None
+}
+
+// Just enough XML handling for <trkpt lat=".." lon=".."><time>..</time></trkpt>
+fn parse_track(data: &str) -> Result<Vec<TrackPoint>> {
+    let mut points = Vec::new();
+
+    for chunk in data.split("<trkpt").skip(1) {
+        let tag_end = chunk.find('>').context("Unterminated <trkpt>")?;
+        let tag = &chunk[..tag_end];
+        let body = chunk.split("</trkpt>").next().unwrap_or_default();
+
+        let lat = attribute(tag, "lat").and_then(|v| v.parse::<f64>().ok());
+        let lon = attribute(tag, "lon").and_then(|v| v.parse::<f64>().ok());
+        let (Some(lat), Some(lon)) = (lat, lon) else {
+            bail!("<trkpt> without a valid lat/lon: <trkpt{}>", tag);
+        };
+
+        let time = body
+            .split_once("<time>")
+            .and_then(|(_, rest)| rest.split_once("</time>"))
+            .and_then(|(time, _)| DateTime::parse_from_rfc3339(time.trim()).ok())
+            .map(|time| time.timestamp_millis() as f64 / 1000.0);
+
+        points.push(TrackPoint {
+            point: Point { lat, lon },
+            time,
+        });
+    }
+
+    if points.is_empty() {
+        bail!("No <trkpt> elements");
+    }
+
+    Ok(points)
+}
+
+impl PositionSource for GpxReplay {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            let track_point = self.points.next()?;
+
+            self.index += 1.0;
+            let timestamp = track_point.time.unwrap_or(self.index);
+            self.pacer.wait_for(timestamp).await;
+
+            let mut fix = GNRMC::from_point(track_point.point);
+            fix.time = track_point.time.and_then(|t| {
+                DateTime::from_timestamp(t.floor() as i64, (t.fract() * 1e9) as u32)
+            });
+
+            Some(fix)
+        })
+    }
+}
@@ -0,0 +1,220 @@
+mod gpx;
+mod nmea;
+mod simulator;
+
+pub use gpx::*;
+pub use nmea::*;
+pub use simulator::*;
+
+use crate::gps::{GNRMC, Gps, GpsOptions};
+use crate::util::BoxFuture;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{Instant, sleep_until};
+
+pub trait PositionSource: Send {
+    // Waits for the next RMC fix. Returns None once a replay has ended; the
+    // GPS module never runs out.
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>>;
+
+    // How often a fix is due, to notice a receiver that went silent. None
+    // for replays, whose gaps are part of the recording.
+    fn fix_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl PositionSource for Gps {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move { Some(self.get().await) })
+    }
+
+    fn fix_interval(&self) -> Option<Duration> {
+        Some(self.fix_interval())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PositionSourceConfig {
+    // The GPS module, on /dev/ttyS0 by default
+    Gps(GpsOptions),
+    GpxReplay(ReplayOptions),
+    NmeaReplay(ReplayOptions),
+    // Walks a route with simulated GPS errors, for testing without a walk
+    Simulator(SimulatorOptions),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReplayOptions {
+    pub path: String,
+    // 2.0 replays twice as fast as recorded
+    pub speed: f64,
+}
+
+impl Default for PositionSourceConfig {
+    fn default() -> Self {
+        PositionSourceConfig::Gps(GpsOptions::default())
+    }
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            speed: 1.0,
+        }
+    }
+}
+
+impl PositionSourceConfig {
+    pub async fn build(&self) -> Result<Box<dyn PositionSource>> {
+        Ok(match self {
+            PositionSourceConfig::Gps(options) => {
+                let mut gps = Gps::new(options)?;
+                gps.init(options).await?;
+                Box::new(gps)
+            }
+            PositionSourceConfig::GpxReplay(options) => Box::new(GpxReplay::open(options)?),
+            PositionSourceConfig::NmeaReplay(options) => Box::new(NmeaReplay::open(options)?),
+            PositionSourceConfig::Simulator(options) => {
+                Box::new(GpsSimulator::new(options.clone())?)
+            }
+        })
+    }
+}
+
+// Spaces out replayed fixes like the original recording. Timestamps are in
+// seconds on any clock; only the differences matter.
+pub(crate) struct Pacer {
+    speed: f64,
+    start: Option<(Instant, f64)>,
+}
+
+impl Pacer {
+    pub(crate) fn new(speed: f64) -> Self {
+        Self {
+            speed: if speed > 0.0 { speed } else { 1.0 },
+            start: None,
+        }
+    }
+
+    pub(crate) fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub(crate) async fn wait_for(&mut self, timestamp: f64) {
+        let (started, first) = *self.start.get_or_insert((Instant::now(), timestamp));
+        let offset = ((timestamp - first) / self.speed).max(0.0);
+
+        sleep_until(started + Duration::from_secs_f64(offset)).await;
+    }
+}
+
+// Time as the fixes tell it, so the filter and the fix quality see the
+// intervals of the walk even in a replay at another speed. Fixes without a
+// time, and time going backwards, fall back to the monotonic clock.
+pub(crate) struct FixClock {
+    now: Instant,
+    last_time: Option<DateTime<Utc>>,
+    last_fix: Instant,
+}
+
+impl FixClock {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            now,
+            last_time: None,
+            last_fix: now,
+        }
+    }
+
+    // Returns the time of the fix and the interval since the previous one
+    pub(crate) fn fix(&mut self, time: Option<DateTime<Utc>>) -> (Instant, Duration) {
+        let elapsed = self.last_fix.elapsed();
+        let dt = self
+            .last_time
+            .zip(time)
+            .and_then(|(last, time)| (time - last).to_std().ok())
+            .unwrap_or(elapsed);
+
+        self.last_fix = Instant::now();
+        self.last_time = time.or(self.last_time);
+        self.now += dt;
+
+        (self.now, dt)
+    }
+
+    // While waiting for a fix, counting on from the last one
+    pub(crate) fn now(&self) -> Instant {
+        self.now + self.last_fix.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::position::{FixClock, GpxReplay, NmeaReplay, PositionSource};
+    use chrono::{TimeZone, Utc};
+    use std::time::Duration;
+    use tokio::time::{Instant, advance};
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_honors_timestamps() {
+        let gpx = r#"<gpx><trk><trkseg>
+            <trkpt lat="33.4235" lon="-111.9328"><time>2025-03-01T12:00:00Z</time></trkpt>
+            <trkpt lat='33.4236' lon='-111.9327'><ele>360</ele><time>2025-03-01T12:00:04Z</time></trkpt>
+        </trkseg></trk></gpx>"#;
+
+        let start = Instant::now();
+        let mut replay = GpxReplay::new(gpx, 2.0).unwrap();
+
+        let first = replay.next_fix().await.unwrap();
+        assert!((first.google_coordinates().lat - 33.4235).abs() < 1e-9);
+        assert!((first.google_coordinates().lon + 111.9328).abs() < 1e-9);
+
+        replay.next_fix().await.unwrap();
+        assert_eq!(start.elapsed().as_secs(), 2);
+        assert!(replay.next_fix().await.is_none());
+
+        let nmea = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\n\
+                    $GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\n\
+                    $GPRMC,123520,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*60\n";
+
+        let start = Instant::now();
+        let mut replay = NmeaReplay::new(nmea, 1.0);
+        assert_eq!(replay.next_fix().await.unwrap().satellites, 8);
+        replay.next_fix().await.unwrap();
+        assert_eq!(start.elapsed().as_secs(), 1);
+        assert!(replay.next_fix().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fix_clock_follows_fix_times() {
+        let mut clock = FixClock::new();
+        let start = clock.now();
+        let at = |secs| Some(Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, secs).unwrap());
+
+        // A replay at 4x: fixes 4 s apart arrive every second
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(clock.fix(at(0)).1, Duration::from_secs(1));
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(clock.fix(at(4)), (start + Duration::from_secs(5), Duration::from_secs(4)));
+
+        // Waiting counts on from there; the next fix's time covers the wait
+        advance(Duration::from_secs(2)).await;
+        assert_eq!(clock.now(), start + Duration::from_secs(7));
+        assert_eq!(clock.fix(at(12)).1, Duration::from_secs(8));
+
+        // Without a time, or going backwards, the monotonic clock counts
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(clock.fix(None).1, Duration::from_secs(1));
+        advance(Duration::from_secs(1)).await;
+        assert_eq!(clock.fix(at(10)).1, Duration::from_secs(1));
+        assert_eq!(clock.now(), start + Duration::from_secs(15));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::gps::{GNRMC, NmeaDecoder, verify_checksum};
+use crate::position::{Pacer, PositionSource, ReplayOptions};
+use crate::util::BoxFuture;
+use anyhow::{Context, Result};
+use log::info;
+use std::fs;
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+// Replays a raw NMEA log captured from the GPS module (e.g. with
+// `cat /dev/ttyS0 > walk.nmea`), paced by the RMC timestamps
+pub struct NmeaReplay {
+    sentences: std::vec::IntoIter<String>,
+    decoder: NmeaDecoder,
+    pacer: Pacer,
+    // Seconds since the first fix; RMC only has the time of day
+    last_time: Option<f64>,
+    day_offset: f64,
+}
+
+impl NmeaReplay {
+    pub fn open(options: &ReplayOptions) -> Result<Self> {
+        let data = fs::read_to_string(&options.path)
+            .with_context(|| format!("Failed to read NMEA log {}", options.path))?;
+
+        Ok(Self::new(&data, options.speed))
+    }
+
+    pub fn new(data: &str, speed: f64) -> Self {
+        let sentences = data
+            .lines()
+            .filter_map(|line| line.find('$').map(|start| line[start..].trim().to_string()))
+            .collect::<Vec<_>>();
+
+        info!("Replaying {} NMEA sentences", sentences.len());
+
+        Self {
+            sentences: sentences.into_iter(),
+            decoder: NmeaDecoder::default(),
+            pacer: Pacer::new(speed),
+            last_time: None,
+            day_offset: 0.0,
+        }
+    }
+
+    // Continuous time of a fix, carrying over midnight
+    fn timestamp(&mut self, time_of_day: f64) -> f64 {
+        if self.last_time.is_some_and(|last| time_of_day < last) {
+            self.day_offset += SECONDS_PER_DAY;
+        }
+        self.last_time = Some(time_of_day);
+
+        time_of_day + self.day_offset
+    }
+}
+
+// hhmmss.ss of an RMC sentence as seconds since midnight
+fn rmc_time_of_day(sentence: &str) -> Option<f64> {
+    let time = verify_checksum(sentence)?.split(',').nth(1)?;
+
+    let hours = time.get(0..2)?.parse::<f64>().ok()?;
+    let minutes = time.get(2..4)?.parse::<f64>().ok()?;
+    let seconds = time.get(4..)?.parse::<f64>().ok()?;
+
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+impl PositionSource for NmeaReplay {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            for sentence in self.sentences.by_ref() {
+                let Some(fix) = self.decoder.handle_sentence(&sentence) else {
+                    continue;
+                };
+
+                if let Some(time_of_day) = rmc_time_of_day(&sentence) {
+                    let timestamp = self.timestamp(time_of_day);
+                    self.pacer.wait_for(timestamp).await;
+                }
+
+                return Some(fix);
+            }
+
+            None
+        })
+    }
+}
@@ -0,0 +1,287 @@
+use crate::gps::GNRMC;
+use crate::overpass::Point;
+use crate::position::PositionSource;
+use crate::util::BoxFuture;
+use anyhow::{Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+// Area where buildings block and reflect signals, e.g. a downtown street
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UrbanCanyon {
+    pub center: Point,
+    pub radius_m: f64,
+    // Noise and HDOP are multiplied by this inside the canyon
+    pub degradation: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SimulatorOptions {
+    // Walked in order; at least two
+    pub waypoints: Vec<Point>,
+    pub speed_mps: f64,
+    pub rate_hz: f64,
+    // Start over from the first waypoint instead of ending the run
+    pub repeat: bool,
+    // Standard deviation of the position error per axis
+    pub noise_m: f64,
+    // Chance per fix of a multipath reflection offsetting the position by
+    // about `multipath_m` for `multipath_secs`
+    pub multipath_probability: f64,
+    pub multipath_m: f64,
+    pub multipath_secs: f64,
+    // Chance per fix of losing the fix for `dropout_secs`
+    pub dropout_probability: f64,
+    pub dropout_secs: f64,
+    pub urban_canyons: Vec<UrbanCanyon>,
+    // Same seed, same run
+    pub seed: Option<u64>,
+}
+
+impl Default for SimulatorOptions {
+    fn default() -> Self {
+        Self {
+            waypoints: Vec::new(),
+            speed_mps: 1.4,
+            rate_hz: 10.0,
+            repeat: false,
+            noise_m: 2.0,
+            multipath_probability: 0.002,
+            multipath_m: 15.0,
+            multipath_secs: 2.0,
+            dropout_probability: 0.001,
+            dropout_secs: 5.0,
+            urban_canyons: Vec::new(),
+            seed: None,
+        }
+    }
+}
+
+// Walks a route of waypoints and reports it through the same fixes as the
+// GPS module, with the kinds of errors seen in the field
+pub struct GpsSimulator {
+    options: SimulatorOptions,
+    rng: StdRng,
+    // Meters walked along the route
+    travelled: f64,
+    // Seconds since the start
+    elapsed: f64,
+    started: DateTime<Utc>,
+    // (east, north) offset in meters and when it ends
+    multipath: Option<((f64, f64), f64)>,
+    dropout_until: Option<f64>,
+}
+
+impl GpsSimulator {
+    pub fn new(options: SimulatorOptions) -> Result<Self> {
+        if options.waypoints.len() < 2 {
+            bail!("The simulator needs at least two waypoints");
+        }
+        if options.speed_mps <= 0.0 || options.rate_hz <= 0.0 {
+            bail!("Simulator speed and rate must be positive");
+        }
+
+        let rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        Ok(Self {
+            options,
+            rng,
+            travelled: 0.0,
+            elapsed: 0.0,
+            started: Utc::now(),
+            multipath: None,
+            dropout_until: None,
+        })
+    }
+
+    fn route_length(&self) -> f64 {
+        self.options
+            .waypoints
+            .windows(2)
+            .map(|w| w[0].distance_m(&w[1]))
+            .sum()
+    }
+
+    // True position after walking `distance` meters along the route
+    fn position_at(&self, mut distance: f64) -> Point {
+        for segment in self.options.waypoints.windows(2) {
+            let length = segment[0].distance_m(&segment[1]);
+
+            if distance <= length && length > 0.0 {
+                let t = distance / length;
+                return Point {
+                    lat: segment[0].lat + (segment[1].lat - segment[0].lat) * t,
+                    lon: segment[0].lon + (segment[1].lon - segment[0].lon) * t,
+                };
+            }
+            distance -= length;
+        }
+
+        *self.options.waypoints.last().unwrap()
+    }
+
+    // Standard normal sample (Box-Muller)
+    fn gaussian(&mut self) -> f64 {
+        let u1: f64 = self.rng.random_range(f64::EPSILON..1.0);
+        let u2: f64 = self.rng.random();
+
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+
+    fn degradation(&self, point: &Point) -> f64 {
+        self.options
+            .urban_canyons
+            .iter()
+            .filter(|canyon| canyon.center.distance_m(point) <= canyon.radius_m)
+            .map(|canyon| canyon.degradation.max(1.0))
+            .fold(1.0, f64::max)
+    }
+
+    fn step(&mut self) -> Option<GNRMC> {
+        let dt = 1.0 / self.options.rate_hz;
+        let length = self.route_length();
+
+        if self.travelled > length {
+            if !self.options.repeat {
+                return None;
+            }
+            self.travelled = 0.0;
+        }
+
+        let truth = self.position_at(self.travelled);
+        self.travelled += self.options.speed_mps * dt;
+        self.elapsed += dt;
+
+        let degradation = self.degradation(&truth);
+        let now = self.elapsed;
+
+        if self.dropout_until.is_some_and(|until| now >= until) {
+            self.dropout_until = None;
+        }
+        // Canyons make losing the fix more likely too
+        if self.dropout_until.is_none()
+            && self
+                .rng
+                .random_bool((self.options.dropout_probability * degradation).min(1.0))
+        {
+            self.dropout_until = Some(now + self.options.dropout_secs);
+        }
+
+        if self.multipath.is_some_and(|(_, until)| now >= until) {
+            self.multipath = None;
+        }
+        if self.multipath.is_none()
+            && self
+                .rng
+                .random_bool((self.options.multipath_probability * degradation).min(1.0))
+        {
+            let direction = self.rng.random_range(0.0..2.0 * PI);
+            let magnitude = self.options.multipath_m * (0.5 + self.rng.random::<f64>());
+            self.multipath = Some((
+                (magnitude * direction.cos(), magnitude * direction.sin()),
+                now + self.options.multipath_secs,
+            ));
+        }
+
+        let mut fix = if self.dropout_until.is_some() {
+            GNRMC::default()
+        } else {
+            let (mut east, mut north) = self.multipath.map_or((0.0, 0.0), |(offset, _)| offset);
+            east += self.gaussian() * self.options.noise_m * degradation;
+            north += self.gaussian() * self.options.noise_m * degradation;
+
+            let mut fix = GNRMC::from_point(Point {
+                lat: truth.lat + north / METERS_PER_DEGREE,
+                lon: truth.lon + east / (METERS_PER_DEGREE * truth.lat.to_radians().cos()),
+            });
+            fix.satellites = (10.0 / degradation).round().max(4.0) as u8;
+            fix.hdop = Some(0.9 * degradation);
+            fix
+        };
+
+        // Receivers keep the time through a dropout
+        fix.time = Some(self.started + TimeDelta::milliseconds((now * 1000.0) as i64));
+
+        Some(fix)
+    }
+}
+
+impl PositionSource for GpsSimulator {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            sleep(Duration::from_secs_f64(1.0 / self.options.rate_hz)).await;
+            self.step()
+        })
+    }
+
+    fn fix_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(1.0 / self.options.rate_hz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::overpass::Point;
+    use crate::position::{GpsSimulator, SimulatorOptions, UrbanCanyon};
+
+    #[test]
+    fn walks_route_with_errors() {
+        let start = Point {
+            lat: 33.4235,
+            lon: -111.9328,
+        };
+        let corner = Point {
+            lat: 33.4235,
+            lon: -111.9318,
+        };
+        let end = Point {
+            lat: 33.4245,
+            lon: -111.9318,
+        };
+
+        // Noise free: follows the route and ends after it
+        let mut simulator = GpsSimulator::new(SimulatorOptions {
+            waypoints: vec![start, corner, end],
+            speed_mps: 10.0,
+            rate_hz: 1.0,
+            noise_m: 0.0,
+            multipath_probability: 0.0,
+            dropout_probability: 0.0,
+            seed: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let fixes = std::iter::from_fn(|| simulator.step()).collect::<Vec<_>>();
+        let length = start.distance_m(&corner) + corner.distance_m(&end);
+        assert_eq!(fixes.len(), (length / 10.0).ceil() as usize);
+        assert!(fixes[0].google_coordinates().distance_m(&start) < 0.01);
+        assert!(fixes.iter().all(|fix| fix.status == 1));
+
+        // Inside a canyon with guaranteed dropouts
+        let mut simulator = GpsSimulator::new(SimulatorOptions {
+            waypoints: vec![start, corner],
+            dropout_probability: 1.0,
+            urban_canyons: vec![UrbanCanyon {
+                center: start,
+                radius_m: 50.0,
+                degradation: 3.0,
+            }],
+            seed: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(simulator.step().unwrap().status, 0);
+    }
+}
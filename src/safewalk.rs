@@ -1,26 +1,108 @@
-use crate::button::Button;
-use crate::espeak::Espeak;
-use crate::gps::{Gps, GpsSimulator, Vector};
-use crate::hazard_analyzer::HazardAnalyzer;
-use crate::motor::Motor;
-use crate::networking::Telemetry;
-use crate::overpass::{OverpassResponse, Point};
-use anyhow::Result;
-use log::{info, warn};
-use std::f64::consts::PI;
+use crate::announcement;
+use crate::button::{Button, ButtonInput};
+use crate::clock::Clock;
+use crate::compass::{Compass, HeadingFusion, Imu};
+use crate::config::Config;
+use crate::dead_reckoning::{self, DeadReckoningConfig, StepDetector};
+use crate::earcon::Earcons;
+use crate::fix_quality::{FixMode, FixQualityMonitor};
+use crate::gps::Vector;
+use crate::position::{FixClock, PositionSource, PositionSourceConfig};
+use crate::kalman::{Estimate, KalmanFilter};
+use crate::hazard_analyzer::{HazardAnalyzer, HazardProfile, HazardSeverity};
+use crate::locale::Catalog;
+use crate::motor::{Motor, MotorOutput};
+use crate::networking::{Control, ControlCommand, MapData, Metrics, Telemetry};
+use crate::overpass::{Element, OverpassResponse, Point, fetch};
+use crate::session::{Entry, Recorder};
+use crate::speech::{Priority, Speech, SpeechOutput, UtteranceId};
+use anyhow::{Result, bail};
+use log::{debug, info, warn};
+use serde::Serialize;
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::exit;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::task::AbortHandle;
-use tokio::time::{Instant, sleep};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep, timeout};
+
+// Within this distance the destination counts as reached
+const ARRIVAL_RADIUS_M: f64 = 10.0;
+
+// The loop goes on without a fix after this many fix intervals, so a silent
+// receiver still counts towards a lost fix
+const FIX_TIMEOUT_INTERVALS: u32 = 10;
+
+// Long enough to turn around slowly a couple of times
+const COMPASS_CALIBRATION: Duration = Duration::from_secs(30);
+
+// Everything the main loop reads from or drives. The device uses GPIO, the
+// configured position source and speech backend; scenario tests use mocks.
+pub struct Hardware {
+    pub front: Arc<dyn MotorOutput>,
+    pub back: Arc<dyn MotorOutput>,
+    pub left: Arc<dyn MotorOutput>,
+    pub right: Arc<dyn MotorOutput>,
+    pub button: Box<dyn ButtonInput>,
+    pub position: Box<dyn PositionSource>,
+    pub speech: Box<dyn SpeechOutput>,
+    pub imu: Option<Box<dyn Imu>>,
+    // Whether the position source's time is the current time and may set the
+    // system clock; recordings and scenarios aren't
+    pub live_time: bool,
+}
+
+impl Hardware {
+    pub async fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            front: Arc::new(Motor::new(26).unwrap()),
+            back: Arc::new(Motor::new(27).unwrap()),
+            left: Arc::new(Motor::new(7).unwrap()),
+            right: Arc::new(Motor::new(5).unwrap()),
+            button: Box::new(Button::new(4)),
+            position: config.position.build().await?,
+            speech: config.speech.backend.build(config.locale),
+            imu: config
+                .compass
+                .sensor
+                .as_ref()
+                .map(|sensor| sensor.build())
+                .transpose()?,
+            live_time: matches!(config.position, PositionSourceConfig::Gps(_)),
+        })
+    }
+}
 
 pub struct SafeWalk {
     vibration_system: VibrationSystem,
-    gps: Gps,
-    button: Button,
-    speak_handle: Option<AbortHandle>,
+    // Taken by `run`
+    position: Option<Box<dyn PositionSource>>,
+    button: Box<dyn ButtonInput>,
+    button_pressed: bool,
+    // What the last press started speaking, stopped on release
+    button_utterance: Option<UtteranceId>,
+    speech: Speech,
+    catalog: Catalog,
+    earcons: Option<Earcons>,
+    last_warned: Option<u64>,
+    commands: Option<mpsc::Receiver<ControlCommand>>,
+    hazard_profile: HazardProfile,
+    haptics_paused: bool,
+    motor_test: Option<JoinHandle<()>>,
+    overpass_refresh: Option<JoinHandle<Result<OverpassResponse>>>,
+    destination: Option<Point>,
+    recorder: Option<Recorder>,
+    filter: KalmanFilter,
+    compass: Option<Compass>,
+    fusion: HeadingFusion,
+    dead_reckoning: DeadReckoningConfig,
+    steps: StepDetector,
+    position_uncertain: bool,
+    fix_quality: FixQualityMonitor,
+    clock: Clock,
 }
 
 #[derive(Clone)]
@@ -32,28 +114,29 @@ struct VibrationSystemSpeeds {
 }
 
 impl VibrationSystemSpeeds {
+    pub fn scaled(&self, factor: f64) -> Self {
+        Self {
+            front: self.front * factor,
+            back: self.back * factor,
+            left: self.left * factor,
+            right: self.right * factor,
+        }
+    }
+
     pub fn vec(&self) -> Vec<f64> {
         vec![self.front, self.right, self.back, self.left]
     }
 }
 
+#[derive(Clone)]
 struct VibrationSystem {
-    front: Motor,
-    back: Motor,
-    left: Motor,
-    right: Motor,
+    front: Arc<dyn MotorOutput>,
+    back: Arc<dyn MotorOutput>,
+    left: Arc<dyn MotorOutput>,
+    right: Arc<dyn MotorOutput>,
 }
 
 impl VibrationSystem {
-    pub fn new(front_pin: u8, back_pin: u8, left_pin: u8, right_pin: u8) -> Self {
-        Self {
-            front: Motor::new(front_pin).unwrap(),
-            back: Motor::new(back_pin).unwrap(),
-            left: Motor::new(left_pin).unwrap(),
-            right: Motor::new(right_pin).unwrap(),
-        }
-    }
-
     pub async fn test(&self) {
         println!("Front motor ON");
         self.front.set(1.0).await;
@@ -126,168 +209,485 @@ impl VibrationSystem {
 }
 
 impl SafeWalk {
-    pub async fn new() -> Self {
-        let mut gps = Gps::new();
-        gps.init().await;
+    pub async fn new(config: &Config) -> Result<Self> {
+        let recorder = match &config.session.record_dir {
+            Some(dir) => Some(Recorder::create(dir)?),
+            None => None,
+        };
+
+        let mut safewalk = Self::with_hardware(config, Hardware::new(config).await?, recorder);
+        if let Some(commands) = Control::take_receiver() {
+            safewalk.set_commands(commands);
+        }
+
+        Ok(safewalk)
+    }
+
+    // With a recorder every input and output is written to the session
+    pub fn with_hardware(config: &Config, hardware: Hardware, recorder: Option<Recorder>) -> Self {
+        let hardware = match &recorder {
+            Some(recorder) => recorder.wrap(hardware),
+            None => hardware,
+        };
 
         Self {
-            vibration_system: VibrationSystem::new(26, 27, 7, 5),
-            gps,
-            button: Button::new(4),
-            speak_handle: None,
+            vibration_system: VibrationSystem {
+                front: hardware.front,
+                back: hardware.back,
+                left: hardware.left,
+                right: hardware.right,
+            },
+            position: Some(hardware.position),
+            button: hardware.button,
+            button_pressed: false,
+            button_utterance: None,
+            speech: Speech::start(
+                hardware.speech,
+                Duration::from_secs(config.speech.repeat_window_secs),
+            ),
+            catalog: Catalog::new(config.locale, config.units),
+            earcons: config
+                .earcons
+                .enabled
+                .then(|| Earcons::new(config.earcons.clone())),
+            last_warned: None,
+            commands: None,
+            hazard_profile: config.hazard_profile,
+            haptics_paused: false,
+            motor_test: None,
+            overpass_refresh: None,
+            destination: None,
+            recorder,
+            filter: KalmanFilter::new(config.kalman.clone()),
+            compass: hardware
+                .imu
+                .map(|imu| Compass::new(imu, config.compass.clone())),
+            fusion: HeadingFusion::new(config.compass.accuracy_deg.to_radians()),
+            dead_reckoning: config.dead_reckoning.clone(),
+            steps: StepDetector::new(config.dead_reckoning.step_threshold_g),
+            position_uncertain: false,
+            fix_quality: FixQualityMonitor::new(config.fix_quality.clone(), Instant::now()),
+            clock: Clock::new(&config.clock, hardware.live_time),
+        }
+    }
+
+    pub fn set_commands(&mut self, commands: mpsc::Receiver<ControlCommand>) {
+        self.commands = Some(commands);
+    }
+
+    fn record(&self, entry: Entry) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(entry);
+        }
+    }
+
+    async fn put<T: Serialize + ?Sized>(&self, key: &str, value: &T) {
+        Telemetry::put(key, value).await;
+
+        if self.recorder.is_some() {
+            self.record(Entry::Telemetry {
+                key: key.to_string(),
+                value: serde_json::to_value(value).unwrap(),
+            });
+        }
+    }
+
+    // Motors are left alone while paused or while the motor test runs
+    fn haptics_active(&self) -> bool {
+        !self.haptics_paused && self.motor_test.as_ref().is_none_or(|test| test.is_finished())
+    }
+
+    async fn apply(&mut self, command: ControlCommand, analyzer: &mut HazardAnalyzer, position: Point) {
+        info!("Control command: {:?}", command);
+
+        match command {
+            ControlCommand::Speak { text, urgent } => {
+                let priority = if urgent { Priority::Urgent } else { Priority::Status };
+                self.speech.say(text, priority);
+            }
+            ControlCommand::PauseHaptics => {
+                self.haptics_paused = true;
+                self.vibration_system.stop().await;
+            }
+            ControlCommand::ResumeHaptics => self.haptics_paused = false,
+            ControlCommand::MotorTest => {
+                if self.haptics_active() {
+                    self.vibration_system.stop().await;
+
+                    let vibration_system = self.vibration_system.clone();
+                    self.motor_test = Some(tokio::spawn(async move { vibration_system.test().await }));
+                }
+            }
+            ControlCommand::SetHazardProfile(profile) => {
+                self.hazard_profile = profile;
+                analyzer.set_profile(profile);
+                self.last_warned = None;
+            }
+            ControlCommand::RefreshOverpass => {
+                if self.overpass_refresh.is_none() {
+                    let bbox = crate::bbox(position.lat, position.lon, 0.015);
+                    self.overpass_refresh = Some(tokio::spawn(fetch(bbox)));
+                }
+            }
+            ControlCommand::CalibrateCompass => match &mut self.compass {
+                Some(compass) => compass.calibrate(COMPASS_CALIBRATION),
+                None => warn!("No compass to calibrate"),
+            },
+            ControlCommand::SetDestination(destination) => {
+                self.destination = destination;
+
+                match destination {
+                    Some(point) => self.put("destination", &[point.lat, point.lon]).await,
+                    None => self.put("destination", &()).await,
+                }
+            }
         }
     }
 
     pub async fn stop(&mut self) {
         self.vibration_system.stop().await;
+
+        if let Some(earcons) = &mut self.earcons {
+            earcons.stop();
+        }
     }
 
     pub async fn main(&mut self) -> Result<()> {
         let data = fs::read_to_string(PathBuf::from("out.json"))?;
 
         let response = serde_json::from_str::<OverpassResponse>(&data)?;
+        self.record(Entry::Map {
+            version: Some(response.osm3s.timestamp_osm_base.clone()),
+            elements: response.elements.clone(),
+        });
 
-        let mut analyzer = HazardAnalyzer::new(33.423528, -111.932806, response.elements);
-
-        let mut prev_location = self.gps.get().await.google_coordinates();
-        sleep(Duration::from_millis(25)).await;
+        self.run(response.elements).await
+    }
 
-        // let mut gps = GpsSimulator::new(
-        //     Point {
-        //         lat: 33.423528,
-        //         lon: -111.932806,
-        //     },
-        //     Point {
-        //         lat: 33.423528,
-        //         lon: -111.932611,
-        //     }
-        // );
+    // Announces a change of fix mode and publishes the current one
+    async fn report_fix_mode(&mut self, change: Option<FixMode>, now: Instant) {
+        if let Some(mode) = change {
+            info!("GPS fix is now {:?}", mode);
+            let priority = match mode {
+                FixMode::Good => Priority::Status,
+                FixMode::Degraded | FixMode::Lost => Priority::Urgent,
+            };
+            self.speech.say(self.catalog.get(mode.message_id(), &[]), priority);
+        }
 
-        // let mut prev_location = gps.get();
+        self.put("fix_mode", &self.fix_quality.mode()).await;
+        self.put("fix_age", &self.fix_quality.age(now).map(|age| age.as_secs_f64()))
+            .await;
+    }
 
-        let mut last_loop = Instant::now();
+    // Announces and signals the hazards around the current estimate
+    async fn guide(
+        &mut self,
+        analyzer: &mut HazardAnalyzer,
+        estimate: Estimate,
+        heading: Option<f64>,
+        compass_heading: Option<f64>,
+    ) {
+        let current_pos = estimate.position;
+
+        // Hazard directions and distances mean little once the position
+        // has drifted further than a crossing is wide
+        let uncertain = estimate.position_accuracy_m > self.dead_reckoning.max_accuracy_m;
+        if uncertain != self.position_uncertain {
+            let (key, priority) = if uncertain {
+                ("position.uncertain", Priority::Urgent)
+            } else {
+                ("position.restored", Priority::Status)
+            };
+            self.speech.say(self.catalog.get(key, &[]), priority);
+            self.position_uncertain = uncertain;
+        }
 
-        loop {
-            // println!("{}", "=".repeat(50));
-            // let response = self.gps.get().await;
-            // println!("{:?}", response);
+        analyzer.update_location(current_pos);
 
-            let location = self.gps.get_with_direction(Some(prev_location)).await;
+        while let Some(command) = self.commands.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.record(Entry::Command {
+                command: command.clone(),
+            });
+            self.apply(command, analyzer, current_pos).await;
+        }
 
-            // Check if simulation ended
-            // if location.1.is_none() {
-            //     println!("Simulation complete - reached destination");
-            //     return Ok(());
-            // }
+        if let Some(refresh) = self.overpass_refresh.take_if(|refresh| refresh.is_finished()) {
+            match refresh.await {
+                Ok(Ok(response)) => {
+                    info!("Loaded {} elements from Overpass", response.elements.len());
+                    MapData::set_elements(&response.elements).await;
+                    self.record(Entry::Map {
+                        version: Some(response.osm3s.timestamp_osm_base.clone()),
+                        elements: response.elements.clone(),
+                    });
+                    analyzer.update_elements(response.elements);
+                }
+                Ok(Err(e)) => warn!("Overpass refresh failed: {}", e),
+                Err(e) => warn!("Overpass refresh task failed: {}", e),
+            }
+        }
 
-            // Only update location if GPS has a valid fix (status = 1 = 'A')
-            let current_pos = if location.0.status == 1 {
-                let pos = location.0.google_coordinates();
-                prev_location = pos; // Update previous location for next bearing calculation
-                pos
-            } else {
-                info!(
-                    "GPS has no valid fix (status={}), using previous location",
-                    location.0.status
-                );
-                prev_location
-            };
+        if let Some(destination) = self.destination {
+            let distance = current_pos.distance_m(&destination);
+            self.put("destination_distance", &distance).await;
 
-            analyzer.update_location(current_pos);
-
-            info!("Current Location: {}, {}", current_pos.lat, current_pos.lon);
-            Telemetry::put_number("latitude", current_pos.lat).await;
-            Telemetry::put_number("longitude", current_pos.lon).await;
-            Telemetry::put_number("heading", location.1.unwrap_or(0.0)).await;
-
-            let reports = analyzer.analyze();
-
-            if self.button.is_pressed() && self.speak_handle.is_none() {
-                let reports_clone = reports.clone();
-                let handle = tokio::spawn(async move {
-                    if let Some(r) = reports_clone {
-                        let nearest = r.first().unwrap();
-                        if nearest.hazard.tags().get("highway") == Some(&"crossing".to_string()) {
-                            Espeak::speak("Hazard ahead pedestrian crossing").await;
-                        } else {
-                            Espeak::speak("Hazard ahead").await;
-                        }
-                    } else {
-                        Espeak::speak("No hazards detected").await;
-                    }
-                })
-                .abort_handle();
-
-                self.speak_handle = Some(handle);
-            } else if !self.button.is_pressed() {
-                if let Some(handle) = &self.speak_handle {
-                    handle.abort(); // Doesnt actually kill the process
-                    self.speak_handle = None;
-                }
+            if distance < ARRIVAL_RADIUS_M {
+                self.speech.say(self.catalog.get("destination.arrived", &[]), Priority::Urgent);
+                self.destination = None;
+                self.put("destination", &()).await;
             }
+        }
 
-            if let Some(mut reports) = reports {
-                reports.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
-                Telemetry::put_vec("hazards", reports.clone()).await;
+        info!("Current Location: {}, {}", current_pos.lat, current_pos.lon);
+        self.put("latitude", &current_pos.lat).await;
+        self.put("longitude", &current_pos.lon).await;
+        MapData::push_position(current_pos).await;
+        self.put("heading", &heading.unwrap_or(0.0)).await;
+        self.put("speed", &estimate.speed_mps).await;
+        self.put("position_accuracy", &estimate.position_accuracy_m).await;
+        self.put("heading_accuracy", &estimate.heading_accuracy).await;
+        if self.compass.is_some() {
+            self.put("compass_heading", &compass_heading).await;
+        }
 
-                let hazard_vector = reports.first().unwrap().vector;
-                let user_heading = location.1.unwrap_or(0.0);
+        let reports = analyzer.analyze();
+        Metrics::hazards_in_range(reports.as_ref().map_or(0, |r| r.len()));
+        MapData::set_hazards(reports.as_deref().unwrap_or_default()).await;
 
-                let mut relative_angle = hazard_vector.rotation - user_heading;
+        let pressed = self.button.is_pressed();
 
-                // Normalize to [-π, π]
-                while relative_angle > PI {
-                    relative_angle -= 2.0 * PI;
-                }
-                while relative_angle < -PI {
-                    relative_angle += 2.0 * PI;
-                }
+        if pressed && !self.button_pressed {
+            let message = match &reports {
+                Some(r) => announcement::describe(r.first().unwrap(), heading, &self.catalog),
+                None => self.catalog.get("no_hazards", &[]),
+            };
+            self.button_utterance = Some(self.speech.say(message, Priority::Status));
+        } else if !pressed
+            && self.button_pressed
+            && let Some(utterance) = self.button_utterance.take()
+        {
+            self.speech.cancel(utterance);
+        }
 
-                // In the relative coordinate system:
-                // 0° = straight ahead
-                // NEGATIVE angles (0° to -180°) = to the RIGHT (clockwise)
-                // POSITIVE angles (0° to 180°) = to the LEFT (counter-clockwise)
-                // ±180° = directly behind
+        self.button_pressed = pressed;
 
-                let relative_vector = Vector::new(relative_angle, hazard_vector.length);
+        if let Some(reports) = reports {
+            self.put("hazards", &reports).await;
 
-                info!(
-                    "Hazard Detected: {:?}",
-                    reports
-                        .first()
-                        .unwrap()
-                        .hazard
-                        .location()
-                        .unwrap()
-                        .first()
-                        .unwrap()
-                );
-                info!("Hazard tags: {:?}", reports.first().unwrap().hazard.tags());
-                info!(
-                    "User heading (radians): {:.4} ({:.1}°)",
-                    user_heading,
-                    user_heading.to_degrees()
-                );
-                info!(
-                    "Hazard absolute angle (radians): {:.4} ({:.1}°)",
-                    hazard_vector.rotation,
-                    hazard_vector.rotation.to_degrees()
+            let nearest = reports.first().unwrap();
+            self.put("hazard_distance", &nearest.distance_m).await;
+            if matches!(nearest.severity, HazardSeverity::High)
+                && self.last_warned != Some(nearest.hazard.id())
+            {
+                debug!("Warning about {:?} {}", nearest.kind, nearest.hazard.id());
+                self.speech.say(
+                    announcement::describe(nearest, heading, &self.catalog),
+                    Priority::Urgent,
                 );
-                info!(
-                    "Relative angle: {:.4} rad ({:.1}°) - Negative=RIGHT, Positive=LEFT",
-                    relative_angle,
-                    relative_angle.to_degrees()
+                self.last_warned = Some(nearest.hazard.id());
+            } else {
+                debug!(
+                    "Not warning about {:?} {}: {:?} severity, already warned: {}",
+                    nearest.kind,
+                    nearest.hazard.id(),
+                    nearest.severity,
+                    self.last_warned == Some(nearest.hazard.id())
                 );
-                info!("Relative Vector: {:?}", relative_vector);
-
-                let speeds = VibrationSystem::get_speeds(relative_vector);
-                info!("Vibration - Front: {:.2}, Back: {:.2}, Left: {:.2}, Right: {:.2}",
-                    speeds.front, speeds.back, speeds.left, speeds.right);
+            }
 
+            let hazard_vector = reports.first().unwrap().vector;
+            let user_heading = heading.unwrap_or(0.0);
+
+            // In the relative coordinate system:
+            // 0° = straight ahead
+            // NEGATIVE angles (0° to -180°) = to the RIGHT (clockwise)
+            // POSITIVE angles (0° to 180°) = to the LEFT (counter-clockwise)
+            // ±180° = directly behind
+
+            let relative_vector = hazard_vector.relative_to(user_heading);
+            let relative_angle = relative_vector.rotation;
+
+            info!(
+                "Hazard Detected: {:?}",
+                reports
+                    .first()
+                    .unwrap()
+                    .hazard
+                    .location()
+                    .unwrap()
+                    .first()
+                    .unwrap()
+            );
+            info!("Hazard tags: {:?}", reports.first().unwrap().hazard.tags());
+            info!(
+                "User heading (radians): {:.4} ({:.1}°)",
+                user_heading,
+                user_heading.to_degrees()
+            );
+            info!(
+                "Hazard absolute angle (radians): {:.4} ({:.1}°)",
+                hazard_vector.rotation,
+                hazard_vector.rotation.to_degrees()
+            );
+            info!(
+                "Relative angle: {:.4} rad ({:.1}°) - Negative=RIGHT, Positive=LEFT",
+                relative_angle,
+                relative_angle.to_degrees()
+            );
+            info!("Relative Vector: {:?}", relative_vector);
+
+            // Weaker, or no, direction cues while the position is unreliable
+            let haptic_scale = self.fix_quality.haptic_scale(!self.position_uncertain);
+            debug!(
+                "Haptic scale {:.2} with the fix {:?}, position uncertain: {}",
+                haptic_scale,
+                self.fix_quality.mode(),
+                self.position_uncertain
+            );
+            let speeds = VibrationSystem::get_speeds(relative_vector).scaled(haptic_scale);
+            info!("Vibration - Front: {:.2}, Back: {:.2}, Left: {:.2}, Right: {:.2}",
+                speeds.front, speeds.back, speeds.left, speeds.right);
+
+            if self.haptics_active() {
                 self.vibration_system.set_speeds(speeds.clone()).await;
-                Telemetry::put_vec("speeds", speeds.vec()).await;
-            } else {
-                // info!("No hazards found");
+            }
+            self.put("speeds", &speeds.vec()).await;
+            Metrics::motor_duty(&speeds.vec());
+
+            if let Some(earcons) = self.earcons.as_mut().filter(|_| haptic_scale > 0.0) {
+                earcons.alert(nearest.kind, relative_angle, nearest.distance_m);
+            }
+        } else {
+            // info!("No hazards found");
+        }
+    }
+
+    // Runs the loop over the given map data until the position source ends
+    pub async fn run(&mut self, elements: Vec<Element>) -> Result<()> {
+        MapData::set_elements(&elements).await;
+        let mut analyzer = HazardAnalyzer::new(33.423528, -111.932806, elements);
+        analyzer.set_profile(self.hazard_profile);
+
+        let Some(mut position) = self.position.take() else {
+            bail!("SafeWalk is already running");
+        };
+        let fix_timeout = position
+            .fix_interval()
+            .map(|interval| interval * FIX_TIMEOUT_INTERVALS);
+        // Kept across timeouts; replays lose a fix when theirs is dropped
+        let mut pending = position.next_fix();
+
+        // The first fix goes through the loop like any other, so a walk that
+        // starts without one is announced
+        let mut fix_clock = FixClock::new();
+        let mut last_loop = Instant::now();
+
+        loop {
+            // println!("{}", "=".repeat(50));
+            // let response = self.gps.get().await;
+            // println!("{:?}", response);
+
+            let next = match fix_timeout {
+                Some(limit) => timeout(limit, &mut pending).await,
+                None => Ok((&mut pending).await),
+            };
+            let fix = match next {
+                Ok(Some(fix)) => Some(fix),
+                Ok(None) => {
+                    info!("Position source ended");
+                    return Ok(());
+                }
+                Err(_) => None,
+            };
+            if fix.is_some() {
+                drop(pending);
+                pending = position.next_fix();
+            }
+            // Intervals come from the fix times, so replays at any speed match
+            // the walk; the next fix covers the time spent waiting
+            let (now, dt) = match &fix {
+                Some(fix) => fix_clock.fix(fix.time),
+                None => (fix_clock.now(), Duration::ZERO),
+            };
+            let dt = dt.as_secs_f64();
+
+            // Without any fix only its age counts, which moves the mode on to
+            // degraded and then lost
+            let change = match &fix {
+                Some(fix) => self.fix_quality.update(fix, now),
+                None => {
+                    warn!("No fix from the position source for {:?}", fix_timeout.unwrap_or_default());
+                    self.fix_quality.tick(now)
+                }
+            };
+            self.report_fix_mode(change, now).await;
+            let fix = fix.unwrap_or_default();
+            self.put("satellites", &fix.satellites).await;
+            if let Some(time) = fix.time {
+                self.clock.sync(&fix);
+                self.put("local_time", &self.clock.local(time).to_rfc3339()).await;
+            }
+            self.put("hdop", &fix.hdop).await;
+
+            // The compass's accelerometer also counts steps
+            let latitude = self.filter.estimate().map_or(0.0, |e| e.position.lat);
+            let reading = self.compass.as_mut().and_then(|compass| {
+                compass
+                    .read(latitude)
+                    .inspect_err(|e| warn!("Compass read failed: {}", e))
+                    .ok()
+            });
+            let compass_heading = reading.map(|(heading, _)| heading);
+            let stepped =
+                reading.is_some_and(|(_, reading)| self.steps.update(&reading, now));
+
+            // Without a valid fix (status = 1 = 'A') each step moves the
+            // position along the compass heading. Without a compass the
+            // filter only carries the last estimate forward.
+            let has_fix = fix.status == 1;
+            if !has_fix {
+                info!(
+                    "GPS has no valid fix (status={}), predicting from the last estimate",
+                    fix.status
+                );
+            }
+            let (estimate, heading) = match compass_heading.filter(|_| !has_fix) {
+                Some(compass_heading) => {
+                    let heading = self.fusion.update(Some(compass_heading), None);
+                    let step = heading.filter(|_| stepped).map(|heading| {
+                        dead_reckoning::step(
+                            &self.dead_reckoning,
+                            heading,
+                            self.fusion.accuracy(),
+                            latitude,
+                        )
+                    });
+                    let estimate = self.filter.dead_reckon(
+                        dt,
+                        step.map(|(displacement, _)| displacement),
+                        step.map_or(0.0, |(_, variance)| variance),
+                    );
+                    (estimate, heading)
+                }
+                None => {
+                    let estimate = self.filter.update(&fix, dt);
+                    // The compass keeps the heading when standing still;
+                    // walking, it is blended with the course over ground
+                    let course = estimate
+                        .and_then(|estimate| estimate.heading.zip(estimate.heading_accuracy))
+                        .filter(|_| has_fix);
+                    (estimate, self.fusion.update(compass_heading, course))
+                }
+            };
+            // Without an estimate there is nothing to guide by yet, but the
+            // loop is still paced and reported
+            match estimate {
+                Some(estimate) => {
+                    self.guide(&mut analyzer, estimate, heading, compass_heading)
+                        .await
+                }
+                None => info!("Waiting for the first valid fix"),
             }
 
             // println!("{}", "=".repeat(50));
@@ -300,6 +700,7 @@ impl SafeWalk {
             if left < 0. {
                 warn!("Loop overrun: {} ms", -left * 1000.);
             }
+            Metrics::loop_completed(dt, left < 0.);
 
             sleep(Duration::from_secs_f64(left.max(0.))).await;
             last_loop = Instant::now();
@@ -0,0 +1,308 @@
+// Headless end-to-end runs of the SafeWalk loop: fixture map data, a scripted
+// walk and button, recording motors and speech, all on tokio's paused clock.
+// Tests assert on the recorded timeline instead of on hardware.
+
+use crate::button::ButtonInput;
+use crate::config::Config;
+use crate::gps::GNRMC;
+use crate::motor::{MotorOutput, Side};
+use crate::overpass::{Element, Point};
+use crate::position::{GpsSimulator, PositionSource, SimulatorOptions};
+use crate::safewalk::{Hardware, SafeWalk};
+use crate::speech::SpeechOutput;
+use crate::util::BoxFuture;
+use anyhow::Result;
+use serde_json::json;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+use tokio::time::{Instant, sleep};
+
+// Runs of the loop share the global telemetry, map data and metrics, so tests
+// that run it take turns
+static LOOP: AsyncMutex<()> = AsyncMutex::const_new(());
+
+pub async fn exclusive() -> MutexGuard<'static, ()> {
+    LOOP.lock().await
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    // A valid fix handed to the loop
+    Fix(Point),
+    Motor(Side, f64),
+    // Recorded when playback starts
+    Spoken(String),
+}
+
+// Everything that happened, stamped with virtual time since the start
+#[derive(Clone)]
+pub struct Timeline {
+    start: Instant,
+    events: Arc<Mutex<Vec<(Duration, Event)>>>,
+}
+
+impl Timeline {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn record(&self, event: Event) {
+        self.events
+            .lock()
+            .unwrap()
+            .push((self.start.elapsed(), event));
+    }
+
+    pub fn events(&self) -> Vec<(Duration, Event)> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn first(&self, predicate: impl Fn(&Event) -> bool) -> Option<Duration> {
+        self.events()
+            .into_iter()
+            .find(|(_, event)| predicate(event))
+            .map(|(at, _)| at)
+    }
+
+    // First time the motor on `side` was set above `power`
+    pub fn motor_above(&self, side: Side, power: f64) -> Option<Duration> {
+        self.first(|event| matches!(event, Event::Motor(s, p) if *s == side && *p > power))
+    }
+
+    // First fix within `radius_m` of `point`
+    pub fn reached(&self, point: Point, radius_m: f64) -> Option<Duration> {
+        self.first(|event| matches!(event, Event::Fix(fix) if fix.distance_m(&point) <= radius_m))
+    }
+
+    pub fn spoken(&self) -> Vec<String> {
+        self.events()
+            .into_iter()
+            .filter_map(|(_, event)| match event {
+                Event::Spoken(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct RecordingMotor {
+    side: Side,
+    timeline: Timeline,
+}
+
+impl MotorOutput for RecordingMotor {
+    fn set(&self, power: f64) -> BoxFuture<'_, ()> {
+        self.timeline.record(Event::Motor(self.side, power));
+        Box::pin(async {})
+    }
+
+    fn off(&self) -> BoxFuture<'_, ()> {
+        self.timeline.record(Event::Motor(self.side, 0.0));
+        Box::pin(async {})
+    }
+}
+
+// Takes a fixed time per word, like a voice would
+struct RecordingSpeech {
+    timeline: Timeline,
+}
+
+impl SpeechOutput for RecordingSpeech {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.timeline.record(Event::Spoken(text.to_string()));
+            sleep(Duration::from_millis(300) * text.split_whitespace().count() as u32).await;
+            Ok(())
+        })
+    }
+}
+
+struct ScriptedButton {
+    start: Instant,
+    presses: Vec<Range<Duration>>,
+}
+
+impl ButtonInput for ScriptedButton {
+    fn is_pressed(&self) -> bool {
+        let now = self.start.elapsed();
+        self.presses.iter().any(|press| press.contains(&now))
+    }
+}
+
+struct RecordingPosition {
+    inner: Box<dyn PositionSource>,
+    timeline: Timeline,
+}
+
+impl PositionSource for RecordingPosition {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            let fix = self.inner.next_fix().await?;
+            if fix.status == 1 {
+                self.timeline.record(Event::Fix(fix.google_coordinates()));
+            }
+            Some(fix)
+        })
+    }
+
+    fn fix_interval(&self) -> Option<Duration> {
+        self.inner.fix_interval()
+    }
+}
+
+pub struct Scenario {
+    config: Config,
+    elements: Vec<Element>,
+    position: Option<Box<dyn PositionSource>>,
+    presses: Vec<Range<Duration>>,
+}
+
+impl Scenario {
+    pub fn new(elements: Vec<Element>) -> Self {
+        Self {
+            config: Config::default(),
+            elements,
+            position: None,
+            presses: Vec::new(),
+        }
+    }
+
+    // Walks the route without GPS errors at 10 fixes per second
+    pub fn walk(self, waypoints: Vec<Point>, speed_mps: f64) -> Self {
+        self.position(
+            GpsSimulator::new(SimulatorOptions {
+                waypoints,
+                speed_mps,
+                noise_m: 0.0,
+                multipath_probability: 0.0,
+                dropout_probability: 0.0,
+                seed: Some(0),
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+    }
+
+    pub fn position(mut self, source: impl PositionSource + 'static) -> Self {
+        self.position = Some(Box::new(source));
+        self
+    }
+
+    // Holds the button down from `at` for `held`
+    pub fn press(mut self, at: Duration, held: Duration) -> Self {
+        self.presses.push(at..at + held);
+        self
+    }
+
+    // Runs the loop until the position source ends, then lets speech finish.
+    // Call from a `#[tokio::test(start_paused = true)]` test.
+    pub async fn run(self) -> Timeline {
+        let _exclusive = exclusive().await;
+        let timeline = Timeline::new();
+        let motor = |side| -> Arc<dyn MotorOutput> {
+            Arc::new(RecordingMotor {
+                side,
+                timeline: timeline.clone(),
+            })
+        };
+
+        let hardware = Hardware {
+            front: motor(Side::Front),
+            back: motor(Side::Back),
+            left: motor(Side::Left),
+            right: motor(Side::Right),
+            button: Box::new(ScriptedButton {
+                start: timeline.start,
+                presses: self.presses,
+            }),
+            position: Box::new(RecordingPosition {
+                inner: self.position.expect("Scenario needs a position source"),
+                timeline: timeline.clone(),
+            }),
+            speech: Box::new(RecordingSpeech {
+                timeline: timeline.clone(),
+            }),
+            imu: None,
+            live_time: false,
+        };
+
+        let mut safewalk = SafeWalk::with_hardware(&self.config, hardware, None);
+        safewalk.run(self.elements).await.unwrap();
+        safewalk.stop().await;
+
+        sleep(Duration::from_secs(30)).await;
+        timeline
+    }
+}
+
+// Fixture map data
+pub fn node(id: u64, point: Point, tags: &[(&str, &str)]) -> Element {
+    let tags = tags
+        .iter()
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect::<serde_json::Map<_, _>>();
+
+    serde_json::from_value(json!({
+        "type": "node",
+        "id": id,
+        "lat": point.lat,
+        "lon": point.lon,
+        "tags": tags,
+    }))
+    .unwrap()
+}
+
+// Meters north and east of `origin`
+pub fn offset(origin: Point, north_m: f64, east_m: f64) -> Point {
+    Point {
+        lat: origin.lat + north_m / 111_320.0,
+        lon: origin.lon + east_m / (111_320.0 * origin.lat.to_radians().cos()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::overpass::Point;
+    use crate::motor::Side;
+    use crate::scenario::{Scenario, node, offset};
+    use std::time::Duration;
+
+    const START: Point = Point {
+        lat: 33.4235,
+        lon: -111.9328,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn crossing_on_the_left() {
+        // Walking east past a crossing 3 m north of the path
+        let crossing = offset(START, 3.0, 40.0);
+        let timeline = Scenario::new(vec![node(
+            1,
+            crossing,
+            &[("highway", "crossing"), ("crossing", "uncontrolled")],
+        )])
+        .walk(vec![START, offset(START, 0.0, 60.0)], 1.4)
+        .press(Duration::from_secs(5), Duration::from_millis(500))
+        .run()
+        .await;
+
+        let abeam = timeline.reached(offset(START, 0.0, 40.0), 0.5).unwrap();
+        let left = timeline.motor_above(Side::Left, 0.5).unwrap();
+        assert!(left < abeam, "left motor at {:?}, abeam at {:?}", left, abeam);
+        assert!(timeline.motor_above(Side::Right, 0.0).is_none());
+
+        // The button press describes the crossing, then the approach warns once
+        let spoken = timeline.spoken();
+        let warnings = spoken
+            .iter()
+            .filter(|text| text.contains("Uncontrolled crossing"))
+            .count();
+        assert_eq!(warnings, 2, "{:?}", spoken);
+        assert_eq!(spoken[0], "Uncontrolled crossing ahead, 33 meters");
+    }
+}
@@ -0,0 +1,646 @@
+use crate::button::ButtonInput;
+use crate::compass::{Imu, ImuReading};
+use crate::config::Config;
+use crate::gps::GNRMC;
+use crate::motor::{MotorOutput, Side};
+use crate::networking::ControlCommand;
+use crate::overpass::Element;
+use crate::position::{Pacer, PositionSource};
+use crate::safewalk::{Hardware, SafeWalk};
+use crate::speech::SpeechOutput;
+use crate::util::BoxFuture;
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep};
+
+// Recording time after the last fix in which speech still queued in the
+// replay gets to start
+const SPEECH_TAIL_SECS: f64 = 3.0;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    // Every walk is recorded to a new session-<time>.jsonl file in this
+    // directory. Unset records nothing.
+    pub record_dir: Option<String>,
+}
+
+// One line of a session file. Fixes, button edges, control commands and map
+// data are inputs; the rest are outputs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    Start { version: String },
+    Map {
+        // Overpass `timestamp_osm_base` of the loaded data
+        version: Option<String>,
+        elements: Vec<Element>,
+    },
+    Fix { fix: GNRMC },
+    Button { pressed: bool },
+    Imu { reading: ImuReading },
+    Command { command: ControlCommand },
+    Motor { side: Side, power: f64 },
+    Speech { text: String },
+    Telemetry { key: String, value: Value },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Record {
+    // Milliseconds since the session started
+    pub t: u64,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+#[derive(Clone)]
+enum Sink {
+    // Written by a blocking task, so recording never waits on the SD card
+    File(mpsc::UnboundedSender<Record>),
+    Memory(Arc<Mutex<Vec<Record>>>),
+}
+
+// Cheap to clone; all clones append to the same session
+#[derive(Clone)]
+pub struct Recorder {
+    start: Instant,
+    sink: Sink,
+}
+
+impl Recorder {
+    pub fn create(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir))?;
+
+        let path = PathBuf::from(dir).join(format!(
+            "session-{}.jsonl",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        info!("Recording session to {}", path.display());
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Record>();
+        tokio::task::spawn_blocking(move || {
+            let mut file = BufWriter::new(file);
+
+            while let Some(record) = rx.blocking_recv() {
+                let flush = matches!(record.entry, Entry::Fix { .. });
+                let line = serde_json::to_string(&record).unwrap();
+                let result = writeln!(file, "{}", line).and_then(|_| {
+                    // Fixes come ten times a second; flushing on them keeps
+                    // little to lose if power is cut mid-walk
+                    if flush { file.flush() } else { Ok(()) }
+                });
+
+                if let Err(e) = result {
+                    warn!("Failed to write session record: {}", e);
+                }
+            }
+        });
+
+        Ok(Self::with_sink(Sink::File(tx)))
+    }
+
+    fn memory() -> Self {
+        Self::with_sink(Sink::Memory(Arc::new(Mutex::new(Vec::new()))))
+    }
+
+    fn with_sink(sink: Sink) -> Self {
+        let recorder = Self {
+            start: Instant::now(),
+            sink,
+        };
+        recorder.record(Entry::Start {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        });
+
+        recorder
+    }
+
+    pub fn record(&self, entry: Entry) {
+        let record = Record {
+            t: self.start.elapsed().as_millis() as u64,
+            entry,
+        };
+
+        match &self.sink {
+            // Only fails once the writer has stopped
+            Sink::File(tx) => {
+                let _ = tx.send(record);
+            }
+            Sink::Memory(records) => records.lock().unwrap().push(record),
+        }
+    }
+
+    fn records(&self) -> Vec<Record> {
+        match &self.sink {
+            Sink::Memory(records) => records.lock().unwrap().clone(),
+            Sink::File(_) => Vec::new(),
+        }
+    }
+
+    // Puts a recording wrapper around every input and output
+    pub fn wrap(&self, hardware: Hardware) -> Hardware {
+        let motor = |side, inner| -> Arc<dyn MotorOutput> {
+            Arc::new(RecordedMotor {
+                side,
+                inner,
+                recorder: self.clone(),
+            })
+        };
+
+        Hardware {
+            front: motor(Side::Front, hardware.front),
+            back: motor(Side::Back, hardware.back),
+            left: motor(Side::Left, hardware.left),
+            right: motor(Side::Right, hardware.right),
+            button: Box::new(RecordedButton {
+                inner: hardware.button,
+                pressed: AtomicBool::new(false),
+                recorder: self.clone(),
+            }),
+            position: Box::new(RecordedPosition {
+                inner: hardware.position,
+                recorder: self.clone(),
+            }),
+            speech: Box::new(RecordedSpeech {
+                inner: hardware.speech,
+                recorder: self.clone(),
+            }),
+            imu: hardware.imu.map(|inner| -> Box<dyn Imu> {
+                Box::new(RecordedImu {
+                    inner,
+                    recorder: self.clone(),
+                })
+            }),
+            live_time: hardware.live_time,
+        }
+    }
+}
+
+struct RecordedMotor {
+    side: Side,
+    inner: Arc<dyn MotorOutput>,
+    recorder: Recorder,
+}
+
+impl MotorOutput for RecordedMotor {
+    fn set(&self, power: f64) -> BoxFuture<'_, ()> {
+        self.recorder.record(Entry::Motor {
+            side: self.side,
+            power,
+        });
+        self.inner.set(power)
+    }
+
+    fn off(&self) -> BoxFuture<'_, ()> {
+        self.recorder.record(Entry::Motor {
+            side: self.side,
+            power: 0.0,
+        });
+        self.inner.off()
+    }
+}
+
+// Records edges only; the loop polls the button every iteration
+struct RecordedButton {
+    inner: Box<dyn ButtonInput>,
+    pressed: AtomicBool,
+    recorder: Recorder,
+}
+
+impl ButtonInput for RecordedButton {
+    fn is_pressed(&self) -> bool {
+        let pressed = self.inner.is_pressed();
+        if self.pressed.swap(pressed, Ordering::Relaxed) != pressed {
+            self.recorder.record(Entry::Button { pressed });
+        }
+
+        pressed
+    }
+}
+
+struct RecordedPosition {
+    inner: Box<dyn PositionSource>,
+    recorder: Recorder,
+}
+
+impl PositionSource for RecordedPosition {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            let fix = self.inner.next_fix().await?;
+            self.recorder.record(Entry::Fix { fix });
+            Some(fix)
+        })
+    }
+
+    fn fix_interval(&self) -> Option<Duration> {
+        self.inner.fix_interval()
+    }
+}
+
+struct RecordedSpeech {
+    inner: Box<dyn SpeechOutput>,
+    recorder: Recorder,
+}
+
+impl SpeechOutput for RecordedSpeech {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        self.recorder.record(Entry::Speech {
+            text: text.to_string(),
+        });
+        self.inner.speak(text)
+    }
+}
+
+struct RecordedImu {
+    inner: Box<dyn Imu>,
+    recorder: Recorder,
+}
+
+impl Imu for RecordedImu {
+    fn read(&mut self) -> Result<ImuReading> {
+        let reading = self.inner.read()?;
+        self.recorder.record(Entry::Imu { reading });
+        Ok(reading)
+    }
+}
+
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+    let data =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| format!("Line {}", i + 1))
+        })
+        .collect()
+}
+
+// Inputs that followed one fix, applied when the fix is replayed. The loop
+// reads the button and drains commands right after each fix, so this puts
+// them in front of the same iteration as in the original session.
+struct Step {
+    t: u64,
+    fix: GNRMC,
+    button: Option<bool>,
+    imu: Option<ImuReading>,
+    commands: Vec<ControlCommand>,
+}
+
+fn steps(records: &[Record]) -> VecDeque<Step> {
+    let mut steps = VecDeque::<Step>::new();
+
+    for record in records {
+        match &record.entry {
+            Entry::Fix { fix } => steps.push_back(Step {
+                t: record.t,
+                fix: *fix,
+                button: None,
+                imu: None,
+                commands: Vec::new(),
+            }),
+            Entry::Button { pressed } => {
+                if let Some(step) = steps.back_mut() {
+                    step.button = Some(*pressed);
+                }
+            }
+            Entry::Imu { reading } => {
+                if let Some(step) = steps.back_mut() {
+                    step.imu = Some(*reading);
+                }
+            }
+            Entry::Command { command } => {
+                if let Some(step) = steps.back_mut() {
+                    step.commands.push(command.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    steps
+}
+
+struct SessionPosition {
+    steps: VecDeque<Step>,
+    pacer: Pacer,
+    button: Arc<AtomicBool>,
+    imu: Arc<Mutex<Option<ImuReading>>>,
+    commands: mpsc::Sender<ControlCommand>,
+}
+
+impl PositionSource for SessionPosition {
+    fn next_fix(&mut self) -> BoxFuture<'_, Option<GNRMC>> {
+        Box::pin(async move {
+            let step = self.steps.pop_front()?;
+            self.pacer.wait_for(step.t as f64 / 1000.0).await;
+
+            if let Some(pressed) = step.button {
+                self.button.store(pressed, Ordering::Relaxed);
+            }
+            *self.imu.lock().unwrap() = step.imu;
+            for command in step.commands {
+                if matches!(command, ControlCommand::RefreshOverpass) {
+                    continue;
+                }
+                let _ = self.commands.try_send(command);
+            }
+
+            Some(step.fix)
+        })
+    }
+}
+
+struct SessionButton(Arc<AtomicBool>);
+
+impl ButtonInput for SessionButton {
+    fn is_pressed(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// The reading recorded after the current fix
+struct SessionImu(Arc<Mutex<Option<ImuReading>>>);
+
+impl Imu for SessionImu {
+    fn read(&mut self) -> Result<ImuReading> {
+        self.0.lock().unwrap().context("No compass reading recorded here")
+    }
+}
+
+struct NullMotor;
+
+impl MotorOutput for NullMotor {
+    fn set(&self, _power: f64) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn off(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+// Takes about as long as a voice at the replay speed, so interruptions
+// happen as on the device
+struct NullSpeech {
+    per_word: Duration,
+}
+
+impl SpeechOutput for NullSpeech {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            sleep(self.per_word * text.split_whitespace().count() as u32).await;
+            Ok(())
+        })
+    }
+}
+
+// Feeds the recorded inputs through the current code and returns the new
+// session, outputs included
+pub async fn replay(recorded: &[Record], config: &Config, speed: f64) -> Result<Vec<Record>> {
+    let mut maps = recorded.iter().filter_map(|record| match &record.entry {
+        Entry::Map { version, elements } => Some((version, elements)),
+        _ => None,
+    });
+    let Some((version, elements)) = maps.next() else {
+        bail!("Session has no map data");
+    };
+    if maps.next().is_some() {
+        warn!("Session reloaded map data; replay uses the first map only");
+    }
+
+    let recorder = Recorder::memory();
+    recorder.record(Entry::Map {
+        version: version.clone(),
+        elements: elements.clone(),
+    });
+
+    let button = Arc::new(AtomicBool::new(false));
+    let imu = Arc::new(Mutex::new(None));
+    let has_imu = recorded
+        .iter()
+        .any(|record| matches!(record.entry, Entry::Imu { .. }));
+    let (tx, rx) = mpsc::channel(32);
+    let pacer = Pacer::new(speed);
+    let per_word = Duration::from_secs_f64(0.3 / pacer.speed());
+    let speech_tail = Duration::from_secs_f64(SPEECH_TAIL_SECS / pacer.speed());
+    let hardware = Hardware {
+        front: Arc::new(NullMotor),
+        back: Arc::new(NullMotor),
+        left: Arc::new(NullMotor),
+        right: Arc::new(NullMotor),
+        button: Box::new(SessionButton(button.clone())),
+        position: Box::new(SessionPosition {
+            steps: steps(recorded),
+            pacer,
+            button,
+            imu: imu.clone(),
+            commands: tx,
+        }),
+        speech: Box::new(NullSpeech { per_word }),
+        imu: has_imu.then(|| Box::new(SessionImu(imu)) as Box<dyn Imu>),
+        live_time: false,
+    };
+
+    let mut safewalk = SafeWalk::with_hardware(config, hardware, Some(recorder.clone()));
+    safewalk.set_commands(rx);
+    safewalk.run(elements.clone()).await?;
+    safewalk.stop().await;
+
+    // Let queued speech start
+    sleep(speech_tail).await;
+
+    Ok(recorder.records())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    // Fix the output followed; None for speech, which is compared in order
+    pub step: Option<usize>,
+    pub output: String,
+    pub recorded: String,
+    pub replayed: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(step) => write!(f, "fix {}: ", step)?,
+            None => write!(f, "speech: ")?,
+        }
+        write!(f, "{} was {}, now {}", self.output, self.recorded, self.replayed)
+    }
+}
+
+// Motor and telemetry outputs per fix, keyed by output name
+fn outputs_per_step(records: &[Record]) -> Vec<Vec<(String, String)>> {
+    let mut steps: Vec<Vec<(String, String)>> = Vec::new();
+
+    for record in records {
+        let output = match &record.entry {
+            Entry::Fix { .. } => {
+                steps.push(Vec::new());
+                continue;
+            }
+            Entry::Motor { side, power } => (format!("{:?} motor", side), format!("{:.3}", power)),
+            Entry::Telemetry { key, value } => (format!("telemetry {}", key), value.to_string()),
+            _ => continue,
+        };
+
+        if let Some(step) = steps.last_mut() {
+            step.push(output);
+        }
+    }
+
+    steps
+}
+
+fn spoken(records: &[Record]) -> Vec<&str> {
+    records
+        .iter()
+        .filter_map(|record| match &record.entry {
+            Entry::Speech { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn diff(recorded: &[Record], replayed: &[Record]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let missing = || "nothing".to_string();
+
+    let before = outputs_per_step(recorded);
+    let after = outputs_per_step(replayed);
+
+    for step in 0..before.len().max(after.len()) {
+        let before = before.get(step).map(Vec::as_slice).unwrap_or_default();
+        let after = after.get(step).map(Vec::as_slice).unwrap_or_default();
+
+        for i in 0..before.len().max(after.len()) {
+            let (b, a) = (before.get(i), after.get(i));
+            if b == a {
+                continue;
+            }
+
+            differences.push(Difference {
+                step: Some(step),
+                output: b.or(a).map(|(name, _)| name.clone()).unwrap_or_default(),
+                recorded: b.map_or_else(missing, |(_, value)| value.clone()),
+                replayed: a.map_or_else(missing, |(_, value)| value.clone()),
+            });
+        }
+    }
+
+    let before = spoken(recorded);
+    let after = spoken(replayed);
+    for i in 0..before.len().max(after.len()) {
+        let (b, a) = (before.get(i), after.get(i));
+        if b != a {
+            differences.push(Difference {
+                step: None,
+                output: format!("phrase {}", i + 1),
+                recorded: b.map_or_else(missing, |text| format!("{:?}", text)),
+                replayed: a.map_or_else(missing, |text| format!("{:?}", text)),
+            });
+        }
+    }
+
+    differences
+}
+
+// `safewalk --replay <session.jsonl> [speed]`: replays a recorded walk and
+// prints how the outputs changed
+pub async fn run_replay(path: &Path, speed: f64, config: &Config) -> Result<()> {
+    let recorded = read(path)?;
+    let replayed = replay(&recorded, config, speed).await?;
+    let differences = diff(&recorded, &replayed);
+
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    println!(
+        "{} differences over {} fixes",
+        differences.len(),
+        steps(&recorded).len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::gps::GNRMC;
+    use crate::motor::Side;
+    use crate::overpass::Point;
+    use crate::scenario::{exclusive, node, offset};
+    use crate::session::{Entry, Record, diff, replay};
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_matches_itself() {
+        let _exclusive = exclusive().await;
+        let start = Point {
+            lat: 33.4235,
+            lon: -111.9328,
+        };
+        let crossing = offset(start, 3.0, 10.0);
+
+        let mut session = vec![Record {
+            t: 0,
+            entry: Entry::Map {
+                version: None,
+                elements: vec![node(1, crossing, &[("highway", "crossing")])],
+            },
+        }];
+        for i in 0..100 {
+            session.push(Record {
+                t: i * 100,
+                entry: Entry::Fix {
+                    fix: GNRMC::from_point(offset(start, 0.0, i as f64 * 0.2)),
+                },
+            });
+            if i == 20 || i == 25 {
+                session.push(Record {
+                    t: i * 100,
+                    entry: Entry::Button { pressed: i == 20 },
+                });
+            }
+        }
+
+        let config = Config::default();
+        let first = replay(&session, &config, 1.0).await.unwrap();
+        let second = replay(&first, &config, 1.0).await.unwrap();
+        assert!(diff(&first, &second).is_empty());
+
+        let mut changed = first.clone();
+        let motor = changed
+            .iter_mut()
+            .find_map(|record| match &mut record.entry {
+                Entry::Motor {
+                    side: Side::Left,
+                    power,
+                } if *power > 0.0 => Some(power),
+                _ => None,
+            })
+            .unwrap();
+        *motor += 0.25;
+
+        let differences = diff(&changed, &second);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].output, "Left motor");
+    }
+}
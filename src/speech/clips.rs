@@ -0,0 +1,79 @@
+use crate::locale::Locale;
+use crate::speech::{SpeechBackend, SpeechOutput, run_to_completion};
+use crate::util::BoxFuture;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClipsOptions {
+    pub dir: PathBuf,
+    // Phrase -> file name in `dir`. Phrases not listed are looked up by their
+    // slug, e.g. "No hazards detected" -> no_hazards_detected.wav
+    pub clips: HashMap<String, String>,
+    // Used for phrases without a clip, e.g. ones containing distances
+    pub fallback: Option<Box<SpeechBackend>>,
+}
+
+impl Default for ClipsOptions {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/home/pi/clips"),
+            clips: HashMap::new(),
+            fallback: None,
+        }
+    }
+}
+
+pub struct Clips {
+    dir: PathBuf,
+    clips: HashMap<String, String>,
+    fallback: Option<Box<dyn SpeechOutput>>,
+}
+
+impl Clips {
+    pub fn new(options: ClipsOptions, locale: Locale) -> Self {
+        Self {
+            dir: options.dir,
+            clips: options.clips,
+            fallback: options.fallback.map(|backend| backend.build(locale)),
+        }
+    }
+
+    fn clip_path(&self, text: &str) -> PathBuf {
+        match self.clips.get(text) {
+            Some(file) => self.dir.join(file),
+            None => self.dir.join(format!("{}.wav", slug(text))),
+        }
+    }
+
+    async fn play(&self, text: &str) -> Result<()> {
+        let path = self.clip_path(text);
+
+        if path.exists() {
+            return run_to_completion(Command::new("aplay").arg("-q").arg(&path)).await;
+        }
+
+        match &self.fallback {
+            Some(fallback) => fallback.speak(text).await,
+            None => bail!("No clip for \"{}\" at {}", text, path.display()),
+        }
+    }
+}
+
+impl SpeechOutput for Clips {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.play(text))
+    }
+}
+
+fn slug(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("_")
+}
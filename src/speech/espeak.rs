@@ -0,0 +1,67 @@
+use crate::speech::{SpeechOutput, run_to_completion};
+use crate::util::BoxFuture;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EspeakOptions {
+    // "espeak" or "espeak-ng"
+    pub program: String,
+    pub voice: Option<String>,
+    // Words per minute
+    pub rate: Option<u32>,
+    // 0-99
+    pub pitch: Option<u32>,
+    // 0-200
+    pub volume: Option<u32>,
+}
+
+impl Default for EspeakOptions {
+    fn default() -> Self {
+        Self {
+            program: "espeak".to_string(),
+            voice: None,
+            rate: None,
+            pitch: None,
+            volume: None,
+        }
+    }
+}
+
+pub struct Espeak {
+    options: EspeakOptions,
+}
+
+impl Espeak {
+    pub fn new(options: EspeakOptions) -> Self {
+        Self { options }
+    }
+
+    fn command(&self, text: &str) -> Command {
+        let mut command = Command::new(&self.options.program);
+
+        if let Some(voice) = &self.options.voice {
+            command.args(["-v", voice]);
+        }
+        if let Some(rate) = self.options.rate {
+            command.args(["-s", &rate.to_string()]);
+        }
+        if let Some(pitch) = self.options.pitch {
+            command.args(["-p", &pitch.to_string()]);
+        }
+        if let Some(volume) = self.options.volume {
+            command.args(["-a", &volume.to_string()]);
+        }
+
+        command.arg(text);
+        command
+    }
+}
+
+impl SpeechOutput for Espeak {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { run_to_completion(&mut self.command(text)).await })
+    }
+}
@@ -0,0 +1,51 @@
+use crate::speech::SpeechOutput;
+use crate::util::BoxFuture;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FileOptions {
+    pub path: PathBuf,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("speech.log"),
+        }
+    }
+}
+
+// Appends each utterance as a line instead of playing audio, for tests and
+// for running on machines without a sound card.
+pub struct FileOutput {
+    path: PathBuf,
+}
+
+impl FileOutput {
+    pub fn new(options: FileOptions) -> Self {
+        Self { path: options.path }
+    }
+
+    async fn append(&self, text: &str) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+
+        file.write_all(format!("{}\n", text).as_bytes()).await?;
+
+        Ok(())
+    }
+}
+
+impl SpeechOutput for FileOutput {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.append(text))
+    }
+}
@@ -0,0 +1,341 @@
+mod clips;
+mod espeak;
+mod file;
+mod piper;
+
+pub use clips::*;
+pub use espeak::*;
+pub use file::*;
+pub use piper::*;
+
+use crate::locale::Locale;
+use crate::util::BoxFuture;
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::time::Instant;
+
+pub trait SpeechOutput: Send + Sync {
+    // Speaks `text` to completion. Dropping the returned future must stop
+    // playback, which is how the queue interrupts and cancels utterances.
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpeechBackend {
+    Espeak(EspeakOptions),
+    Piper(PiperOptions),
+    Clips(ClipsOptions),
+    File(FileOptions),
+}
+
+impl Default for SpeechBackend {
+    fn default() -> Self {
+        SpeechBackend::Espeak(EspeakOptions::default())
+    }
+}
+
+impl SpeechBackend {
+    pub fn build(&self, locale: Locale) -> Box<dyn SpeechOutput> {
+        match self.clone() {
+            SpeechBackend::Espeak(mut options) => {
+                options
+                    .voice
+                    .get_or_insert_with(|| locale.espeak_voice().to_string());
+                Box::new(Espeak::new(options))
+            }
+            SpeechBackend::Piper(options) => Box::new(Piper::new(options)),
+            SpeechBackend::Clips(options) => Box::new(Clips::new(options, locale)),
+            SpeechBackend::File(options) => Box::new(FileOutput::new(options)),
+        }
+    }
+}
+
+// Runs a playback command with its output discarded. The child is killed when
+// the future is dropped, so cancelling the caller actually stops the audio.
+pub(crate) async fn run_to_completion(command: &mut Command) -> Result<()> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+
+    let status = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program))?
+        .wait()
+        .await?;
+
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+
+    Ok(())
+}
+
+// Ordered from least to most important; a new utterance interrupts the
+// current one only if its priority is strictly higher.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Status,
+    Urgent,
+}
+
+// Identifies the utterance of one `Speech::say`, for cancelling just that one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtteranceId(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utterance {
+    pub id: UtteranceId,
+    pub text: String,
+    pub priority: Priority,
+    seq: u64,
+}
+
+impl Ord for Utterance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Utterance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub struct SpeechQueue {
+    pending: BinaryHeap<Utterance>,
+    // When each phrase was last spoken, and at which priority
+    recent: HashMap<String, (Instant, Priority)>,
+    repeat_window: Duration,
+    next_seq: u64,
+}
+
+impl SpeechQueue {
+    pub fn new(repeat_window: Duration) -> Self {
+        Self {
+            pending: BinaryHeap::new(),
+            recent: HashMap::new(),
+            repeat_window,
+            next_seq: 0,
+        }
+    }
+
+    // Returns false if the phrase was dropped as a duplicate of one that is
+    // already queued or was spoken within the repeat window, at the same or
+    // a higher priority.
+    pub fn push(&mut self, id: UtteranceId, text: String, priority: Priority, now: Instant) -> bool {
+        if let Some((spoken_at, spoken_priority)) = self.recent.get(&text)
+            && now.duration_since(*spoken_at) < self.repeat_window
+            && *spoken_priority >= priority
+        {
+            return false;
+        }
+
+        if let Some(existing) = self.pending.iter().find(|u| u.text == text) {
+            if existing.priority >= priority {
+                return false;
+            }
+
+            // Re-queue the phrase at the higher priority
+            self.pending.retain(|u| u.text != text);
+        }
+
+        self.next_seq += 1;
+        self.pending.push(Utterance {
+            id,
+            text,
+            priority,
+            seq: self.next_seq,
+        });
+
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<Utterance> {
+        self.pending.pop()
+    }
+
+    pub fn mark_spoken(&mut self, utterance: &Utterance, now: Instant) {
+        self.recent
+            .retain(|_, (spoken_at, _)| now.duration_since(*spoken_at) < self.repeat_window);
+        self.recent
+            .insert(utterance.text.clone(), (now, utterance.priority));
+    }
+
+    pub fn remove(&mut self, id: UtteranceId) {
+        self.pending.retain(|u| u.id != id);
+    }
+}
+
+enum SpeechCommand {
+    Say(UtteranceId, String, Priority),
+    Cancel(UtteranceId),
+}
+
+// Handle to the background speech task. Cloning is cheap; all clones feed the
+// same queue.
+#[derive(Clone)]
+pub struct Speech {
+    tx: UnboundedSender<SpeechCommand>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Speech {
+    pub fn start(output: Box<dyn SpeechOutput>, repeat_window: Duration) -> Self {
+        let (tx, rx) = unbounded_channel();
+
+        tokio::spawn(run(output, rx, SpeechQueue::new(repeat_window)));
+
+        Self {
+            tx,
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn say(&self, text: impl Into<String>, priority: Priority) -> UtteranceId {
+        let id = UtteranceId(self.next_id.fetch_add(1, AtomicOrdering::Relaxed));
+        let _ = self.tx.send(SpeechCommand::Say(id, text.into(), priority));
+
+        id
+    }
+
+    // Stops or unqueues the given utterance; everything else keeps playing
+    pub fn cancel(&self, id: UtteranceId) {
+        let _ = self.tx.send(SpeechCommand::Cancel(id));
+    }
+}
+
+async fn run(
+    output: Box<dyn SpeechOutput>,
+    mut rx: UnboundedReceiver<SpeechCommand>,
+    mut queue: SpeechQueue,
+) {
+    loop {
+        let Some(utterance) = queue.pop() else {
+            match rx.recv().await {
+                Some(SpeechCommand::Say(id, text, priority)) => {
+                    queue.push(id, text, priority, Instant::now());
+                }
+                Some(SpeechCommand::Cancel(id)) => queue.remove(id),
+                None => return,
+            }
+            continue;
+        };
+
+        info!("Speaking: {}", utterance.text);
+        let speaking = output.speak(&utterance.text);
+        tokio::pin!(speaking);
+
+        loop {
+            tokio::select! {
+                result = &mut speaking => {
+                    if let Err(e) = result {
+                        warn!("Speech failed: {}", e);
+                    }
+                    queue.mark_spoken(&utterance, Instant::now());
+                    break;
+                }
+                command = rx.recv() => match command {
+                    Some(SpeechCommand::Say(id, text, priority)) => {
+                        // A more urgent repeat interrupts and starts over
+                        if text == utterance.text && priority <= utterance.priority {
+                            continue;
+                        }
+
+                        let queued = queue.push(id, text, priority, Instant::now());
+                        if queued && priority > utterance.priority {
+                            // Dropping `speaking` stops playback
+                            info!("Interrupting \"{}\"", utterance.text);
+                            break;
+                        }
+                    }
+                    Some(SpeechCommand::Cancel(id)) => {
+                        queue.remove(id);
+                        if utterance.id == id {
+                            break;
+                        }
+                    }
+                    None => return,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::speech::{Priority, SpeechQueue, UtteranceId};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn urgent_before_status() {
+        let now = Instant::now();
+        let mut queue = SpeechQueue::new(Duration::from_secs(5));
+
+        queue.push(UtteranceId(1), "status one".to_string(), Priority::Status, now);
+        queue.push(UtteranceId(2), "crossing".to_string(), Priority::Urgent, now);
+        queue.push(UtteranceId(3), "status two".to_string(), Priority::Status, now);
+
+        assert_eq!(queue.pop().unwrap().text, "crossing");
+        assert_eq!(queue.pop().unwrap().text, "status one");
+        assert_eq!(queue.pop().unwrap().text, "status two");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn duplicates_dropped() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(1);
+        let mut queue = SpeechQueue::new(Duration::from_secs(5));
+        let push = |queue: &mut SpeechQueue, id, priority, now| {
+            queue.push(UtteranceId(id), "crossing".to_string(), priority, now)
+        };
+
+        assert!(push(&mut queue, 1, Priority::Status, now));
+        assert!(!push(&mut queue, 2, Priority::Status, now));
+        assert!(push(&mut queue, 3, Priority::Urgent, now));
+
+        let spoken = queue.pop().unwrap();
+        assert_eq!(spoken.priority, Priority::Urgent);
+        assert!(queue.pop().is_none());
+        queue.mark_spoken(&spoken, now);
+
+        assert!(!push(&mut queue, 4, Priority::Urgent, later));
+        assert!(push(&mut queue, 5, Priority::Urgent, now + Duration::from_secs(6)));
+
+        // Spoken as status, it is still repeated when it becomes urgent
+        let mut queue = SpeechQueue::new(Duration::from_secs(5));
+        push(&mut queue, 6, Priority::Status, now);
+        let spoken = queue.pop().unwrap();
+        queue.mark_spoken(&spoken, now);
+        assert!(!push(&mut queue, 7, Priority::Status, later));
+        assert!(push(&mut queue, 8, Priority::Urgent, later));
+    }
+
+    #[test]
+    fn cancel_by_id() {
+        let now = Instant::now();
+        let mut queue = SpeechQueue::new(Duration::from_secs(5));
+
+        queue.push(UtteranceId(1), "no hazards".to_string(), Priority::Status, now);
+        queue.push(UtteranceId(2), "GPS signal weak".to_string(), Priority::Status, now);
+        queue.remove(UtteranceId(1));
+
+        assert_eq!(queue.pop().unwrap().id, UtteranceId(2));
+        assert!(queue.pop().is_none());
+    }
+}
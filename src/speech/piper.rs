@@ -0,0 +1,102 @@
+use crate::speech::SpeechOutput;
+use crate::util::BoxFuture;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PiperOptions {
+    pub program: String,
+    pub model: PathBuf,
+    // Must match the model's sample rate, see the model's .onnx.json
+    pub sample_rate: u32,
+    pub speaker: Option<u32>,
+    // Larger is slower speech
+    pub length_scale: Option<f32>,
+}
+
+impl Default for PiperOptions {
+    fn default() -> Self {
+        Self {
+            program: "piper".to_string(),
+            model: PathBuf::from("/home/pi/voices/en_US-lessac-medium.onnx"),
+            sample_rate: 22050,
+            speaker: None,
+            length_scale: None,
+        }
+    }
+}
+
+// Local neural TTS: piper renders raw PCM to stdout, which is piped into aplay
+pub struct Piper {
+    options: PiperOptions,
+}
+
+impl Piper {
+    pub fn new(options: PiperOptions) -> Self {
+        Self { options }
+    }
+
+    async fn run(&self, text: &str) -> Result<()> {
+        let mut piper = Command::new(&self.options.program);
+        piper
+            .arg("--model")
+            .arg(&self.options.model)
+            .arg("--output-raw");
+
+        if let Some(speaker) = self.options.speaker {
+            piper.args(["--speaker", &speaker.to_string()]);
+        }
+        if let Some(length_scale) = self.options.length_scale {
+            piper.args(["--length_scale", &length_scale.to_string()]);
+        }
+
+        let mut piper = piper
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.options.program))?;
+
+        let pcm: Stdio = piper.stdout.take().unwrap().try_into()?;
+
+        let mut aplay = Command::new("aplay")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1"])
+            .args(["-r", &self.options.sample_rate.to_string()])
+            .stdin(pcm)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start aplay")?;
+
+        // Dropping stdin closes it so piper knows the text is complete
+        let mut stdin = piper.stdin.take().unwrap();
+        stdin.write_all(text.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+        drop(stdin);
+
+        let piper_status = piper.wait().await?;
+        let aplay_status = aplay.wait().await?;
+
+        if !piper_status.success() {
+            bail!("{} exited with {}", self.options.program, piper_status);
+        }
+        if !aplay_status.success() {
+            bail!("aplay exited with {}", aplay_status);
+        }
+
+        Ok(())
+    }
+}
+
+impl SpeechOutput for Piper {
+    fn speak<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.run(text))
+    }
+}
@@ -0,0 +1,5 @@
+use std::future::Future;
+use std::pin::Pin;
+
+// Future returned by the hardware traits, which need to stay object safe
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;